@@ -1,4 +1,6 @@
 
+use crate::client::*;
+
 #[derive(Clone)]
 pub enum BranchSpec {
     ByBranchId(u32),
@@ -13,7 +15,7 @@ impl <'a> BranchSpec {
                 id
                     .parse::<u32>()
                     .map_err(|_| "project-id should be a number")
-                    .map(|branch_id| BranchSpec::ByBranchId(branch_id))
+                    .map(BranchSpec::ByBranchId)
             } else { Err("branch-id cannot be empty") }
         } else if branch_spec_string.starts_with("branch=") {
             if let Some(("branch", name)) = branch_spec_string.split_once('=') {
@@ -21,6 +23,36 @@ impl <'a> BranchSpec {
             } else { Err("branch-name cannot be empty") }
         } else { Err("Must contain branch or branchId identifier.") }
     }
+
+    /// Look up the single branch in the given project that this spec refers to.
+    ///
+    /// Branch names are matched exactly, including case; if more than one branch has the name,
+    /// the spec is ambiguous and this returns an error.
+    pub fn resolve(&self, client: &ApiClient, project_id: u32) -> ApiResult<ApiBranch> {
+        match *self {
+            BranchSpec::ByBranchId(branch_id) => {
+                client.get_branch(project_id, branch_id)?.ok_or_else(|| {
+                    ApiError::BranchResolution(format!("project {} has no branch with id {}", project_id, branch_id))
+                })
+            },
+            BranchSpec::ByBranchName(ref name) => {
+                let filter = ApiBranchFilter { project_id, name: Some(name), name_match: BranchNameMatch::Exact };
+                single_branch(project_id, name, client.query_branches_for_project(&filter)?)
+            },
+        }
+    }
+}
+
+/// Pick the one branch out of those the server found named `name`, or explain why there isn't exactly one.
+fn single_branch(project_id: u32, name: &str, mut candidates: Vec<ApiBranch>) -> ApiResult<ApiBranch> {
+    match candidates.len() {
+        0 => Err(ApiError::BranchResolution(format!("project {} has no branch named \"{}\"", project_id, name))),
+        1 => Ok(candidates.remove(0)),
+        _ => {
+            let ids: Vec<String> = candidates.iter().map(|b| b.id.to_string()).collect();
+            Err(ApiError::BranchResolution(format!("branch name \"{}\" is ambiguous in project {}; branches {} all have it", name, project_id, ids.join(", "))))
+        },
+    }
 }
 
 #[derive(Clone)]
@@ -30,6 +62,34 @@ pub struct ProjectContext {
     pub api_string: String,
 }
 impl <'a> ProjectContext {
+    /// Check this context's branch spec (if any) against the server, returning an equivalent
+    /// context that refers to the branch by its ID.
+    pub fn resolve(&self, client: &ApiClient) -> ApiResult<ProjectContext> {
+        match self.branch_spec {
+            None => Ok(self.clone()),
+            Some(ref spec) => spec.resolve(client, self.project_id)
+                .map(|branch| ProjectContext::for_branch_id(self.project_id, branch.id)),
+        }
+    }
+
+    /// Context for the branch with the given ID, within the given project.
+    pub fn for_branch_id(project_id: u32, branch_id: u32) -> ProjectContext {
+        ProjectContext {
+            project_id,
+            branch_spec: Some(BranchSpec::ByBranchId(branch_id)),
+            api_string: format!("{};branchId={}", project_id, branch_id),
+        }
+    }
+
+    /// Context for the branch with the given name, within the given project.
+    pub fn for_branch_name(project_id: u32, branch_name: &str) -> ProjectContext {
+        ProjectContext {
+            project_id,
+            branch_spec: Some(BranchSpec::ByBranchName(branch_name.to_string())),
+            api_string: format!("{};branch={}", project_id, branch_name),
+        }
+    }
+
     pub fn parse(project_context_input: &'a str) -> Result<ProjectContext, &'a str> {
         let input = project_context_input;
         if !input.contains(';') {
@@ -53,4 +113,19 @@ impl <'a> ProjectContext {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_single_branch(){
+    let branch = |id: u32| ApiBranch { id, name: "main".to_string(), project_id: 5, is_default: false };
+
+    assert!(single_branch(5, "main", vec![branch(1)]).unwrap().id == 1);
+    match single_branch(5, "main", vec![]) {
+        Err(ApiError::BranchResolution(msg)) => assert!(msg == "project 5 has no branch named \"main\""),
+        _ => panic!("expected a BranchResolution error"),
+    }
+    match single_branch(5, "main", vec![branch(1), branch(2)]) {
+        Err(ApiError::BranchResolution(msg)) => assert!(msg == "branch name \"main\" is ambiguous in project 5; branches 1, 2 all have it"),
+        _ => panic!("expected a BranchResolution error"),
+    }
+}
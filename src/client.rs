@@ -16,17 +16,18 @@
 
 use crate::branching::*;
 use crate::config::ClientConfig;
-use reqwest;
 use::reqwest::Method;
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
-use serde_json;
-use std;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
 use std::fmt::Debug;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 
 /// Project filter criteria used with `ApiClient::query_projects` to define project filter criteria.
@@ -48,13 +49,53 @@ pub struct ApiProject {
     pub parent_id: Option<u32>,
 }
 
-/// Branch filter criteria used with `ApiClient::query_branches` to define project filter criteria.
+/// A custom project metadata field, as defined by a Code Dx admin.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiMetadataField {
+    pub id: String,
+    pub name: String,
+    /// e.g. "text", "dropdown", "tag"
+    #[serde(rename = "type")]
+    pub field_type: String,
+}
+
+/// Branch filter criteria used with `ApiClient::query_branches_for_project` to define branch filter criteria.
 #[derive(Debug, Serialize)]
 pub struct ApiBranchFilter<'a> {
+    #[serde(skip)]
     pub project_id: u32,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub name: Option<&'a str>
+    pub name: Option<&'a str>,
+
+    /// How `name` is compared to each branch's name.
+    #[serde(rename = "nameMatch")]
+    pub name_match: BranchNameMatch,
+}
+
+/// Ways of matching a branch name in an `ApiBranchFilter`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BranchNameMatch {
+    /// Case-insensitive substring match
+    Contains,
+    /// Case-sensitive match of the whole name
+    Exact,
+    /// Case-insensitive match at the start of the name
+    Prefix,
+    /// The name must match the given regular expression
+    Regex,
+}
+impl BranchNameMatch {
+    pub fn parse(input: &str) -> Result<BranchNameMatch, &'static str> {
+        match input {
+            "contains" => Ok(BranchNameMatch::Contains),
+            "exact" => Ok(BranchNameMatch::Exact),
+            "prefix" => Ok(BranchNameMatch::Prefix),
+            "regex" => Ok(BranchNameMatch::Regex),
+            _ => Err("name match should be one of contains, exact, prefix, or regex"),
+        }
+    }
 }
 
 /// A branch provided by the Code Dx API.
@@ -68,6 +109,192 @@ pub struct ApiBranch {
     pub is_default: bool,
 }
 
+/// An analysis provided by the Code Dx API.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiAnalysis {
+    pub id: u32,
+    #[serde(rename = "projectId")]
+    pub project_id: u32,
+    pub name: Option<String>,
+    /// e.g. "queued", "running", "complete", "failed"
+    pub state: String,
+    #[serde(rename = "creationTime")]
+    pub creation_time: String,
+    #[serde(rename = "branchId", default)]
+    pub branch_id: Option<u32>,
+    /// The files (and git source, if any) that went into the analysis.
+    #[serde(rename = "toolInputs", default)]
+    pub tool_inputs: Vec<ApiToolInput>,
+}
+
+/// One of the inputs to an `ApiAnalysis`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiToolInput {
+    /// Name of the uploaded file
+    #[serde(rename = "fileName")]
+    pub file_name: Option<String>,
+    /// The tool whose results were found in the file, if any
+    pub tool: Option<String>,
+}
+
+/// Finding filter criteria used with `ApiClient::query_findings` and `ApiClient::count_findings`.
+///
+/// Each list-valued criterion matches findings having any one of the given values;
+/// separate criteria must all match.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ApiFindingFilter<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Vec<&'a str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Vec<&'a str>>,
+
+    #[serde(rename = "detectionMethod", skip_serializing_if = "Option::is_none")]
+    pub detection_method: Option<Vec<&'a str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<Vec<&'a str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwe: Option<Vec<u32>>,
+
+    /// Glob pattern matched against the finding's primary location path, e.g. `src/main/**/*.java`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<&'a str>,
+
+    /// IDs of specific findings
+    #[serde(rename = "finding", skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<u32>>,
+
+    /// Only findings that were seen in the given analysis
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analysis: Option<u32>,
+}
+impl <'a> ApiFindingFilter<'a> {
+    /// Whether this filter matches every finding.
+    pub fn is_empty(&self) -> bool {
+        self.severity.is_none() && self.status.is_none() && self.detection_method.is_none() && self.tool.is_none()
+            && self.cwe.is_none() && self.path.is_none() && self.rule.is_none() && self.ids.is_none() && self.analysis.is_none()
+    }
+}
+
+/// A status change for a set of findings, used with `ApiClient::update_findings_status`.
+#[derive(Debug, Serialize)]
+pub struct ApiStatusUpdate<'a> {
+    /// e.g. "false-positive", "ignored", "mitigated", "escalated", or "assigned"
+    pub status: &'a str,
+    /// Explanation recorded in each finding's history
+    pub comment: &'a str,
+    /// Username of the person to assign the findings to, for the "assigned" status
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<&'a str>,
+}
+
+/// Page selection for endpoints that return potentially large lists, like the findings query.
+#[derive(Debug, Serialize, Clone, Copy)]
+pub struct ApiPagination {
+    /// 1-based page number
+    pub page: u32,
+    #[serde(rename = "perPage")]
+    pub per_page: u32,
+}
+
+/// Something the Code Dx API refers to by both an ID and a display name, e.g. a severity or detection method.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiNamedRef {
+    pub id: Option<u32>,
+    pub name: String,
+}
+
+/// A location within a codebase, as attached to a finding.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiFindingLocation {
+    pub path: String,
+    #[serde(rename = "lineRange")]
+    pub line_range: Option<ApiLineRange>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiLineRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// A finding provided by the Code Dx API's findings query, or in more detail by `ApiClient::get_finding`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiFinding {
+    pub id: u32,
+    /// The rule (a.k.a. "descriptor") that this finding is an instance of.
+    pub descriptor: Option<ApiNamedRef>,
+    pub severity: Option<ApiNamedRef>,
+    pub status: Option<String>,
+    #[serde(rename = "detectionMethod")]
+    pub detection_method: Option<ApiNamedRef>,
+    #[serde(rename = "primaryLocation")]
+    pub primary_location: Option<ApiFindingLocation>,
+
+    // the rest are only filled in by `get_finding`
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwe: Option<u32>,
+    /// Explanation of the rule, and usually how to fix the problem
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Every location the finding was seen at, including the primary location
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locations: Vec<ApiFindingLocation>,
+    /// The individual tool results that were correlated into this finding
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<ApiToolResult>,
+}
+
+/// One tool's report of a problem, as correlated into an `ApiFinding`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiToolResult {
+    pub id: u32,
+    pub tool: Option<String>,
+    /// The tool's own name for the rule, which may differ from the finding's descriptor
+    pub rule: Option<String>,
+    pub severity: Option<ApiNamedRef>,
+    /// What the tool said about the problem
+    pub message: Option<String>,
+    pub location: Option<ApiFindingLocation>,
+}
+
+/// A comment on a finding.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiFindingComment {
+    pub id: u32,
+    pub author: Option<String>,
+    pub created: String,
+    pub content: String,
+}
+
+/// Something that happened to a finding, e.g. it was first seen, its status changed, or someone commented on it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ApiFindingHistoryEntry {
+    /// e.g. "created", "status-change", "comment"
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub date: String,
+    pub user: Option<String>,
+    /// The new status, for a status change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// The comment, for a comment or a status change that came with one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// The response the server gives for "count" style endpoints.
+#[derive(Debug, Deserialize)]
+struct ApiCountResponse {
+    count: u64
+}
+
 /// The response the server gives when you successfully start an analysis via the "stable" start-analysis endpoint.
 #[derive(Debug, Deserialize)]
 pub struct ApiAnalysisJobResponse {
@@ -84,8 +311,68 @@ pub struct ApiAnalysisWithGitSourceJobResponse {
     pub job_id: String
 }
 
+/// The response the server gives when it starts a job that isn't tied to an analysis, e.g. report generation.
+#[derive(Debug, Deserialize)]
+pub struct ApiJobResponse {
+    #[serde(rename = "jobId")]
+    pub job_id: String
+}
+
+/// Report type and format-specific options, used with `ApiClient::generate_report`.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum ApiReportConfig {
+    Pdf {
+        /// "simple" or "detailed"
+        #[serde(rename = "summaryMode")]
+        summary_mode: String,
+        /// "none", "simple", or "with-source"
+        #[serde(rename = "detailsMode")]
+        details_mode: String,
+        #[serde(rename = "includeResultDetails")]
+        include_result_details: bool,
+        #[serde(rename = "includeComments")]
+        include_comments: bool,
+        #[serde(rename = "includeRequestResponse")]
+        include_request_response: bool,
+    },
+    Csv {
+        /// Names of the columns to include, e.g. "id", "severity", "location"
+        columns: Vec<String>,
+    },
+    Xml {
+        #[serde(rename = "includeStandards")]
+        include_standards: bool,
+        #[serde(rename = "includeSource")]
+        include_source: bool,
+        #[serde(rename = "includeRuleDescriptions")]
+        include_rule_descriptions: bool,
+    },
+    Nessus {
+        #[serde(rename = "defaultHost")]
+        default_host: String,
+        #[serde(rename = "operatingSystem")]
+        operating_system: String,
+        #[serde(rename = "macAddress")]
+        mac_address: String,
+        #[serde(rename = "netBIOSName")]
+        netbios_name: String,
+    },
+}
+impl ApiReportConfig {
+    /// The report type, as it appears in the report generation endpoint's URL.
+    pub fn report_type(&self) -> &'static str {
+        match *self {
+            ApiReportConfig::Pdf { .. } => "pdf",
+            ApiReportConfig::Csv { .. } => "csv",
+            ApiReportConfig::Xml { .. } => "xml",
+            ApiReportConfig::Nessus { .. } => "nessus",
+        }
+    }
+}
+
 /// Enumeration representing the 5 possible statuses a Code Dx "job" may be in.
-#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum JobStatus {
     Queued,
@@ -96,22 +383,14 @@ pub enum JobStatus {
 }
 impl JobStatus {
     pub fn is_ready(&self) -> bool {
-        match *self {
-            JobStatus::Completed => true,
-            JobStatus::Failed => true,
-            _ => false
-        }
+        matches!(*self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
     }
-    #[allow(dead_code)]
     pub fn is_success(&self) -> bool {
-        match *self {
-            JobStatus::Completed => true,
-            _ => false
-        }
+        matches!(*self, JobStatus::Completed)
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct JobStatusResponse {
     /// ID of the requested job.
     ///
@@ -122,18 +401,59 @@ pub struct JobStatusResponse {
     /// The actual job status.
     pub status: JobStatus,
 
-    // there are some optional fields like "progress", "blockedBy", and "reason"
-    // which are present depending on the status, but they aren't necessary for
-    // our use case, so I'm not going to model them.
+    /// How far along a running job is, as a percentage.
+    ///
+    /// Not every job reports its progress, so anything other than a number is treated as unknown
+    /// rather than failing the whole status check.
+    #[serde(default, deserialize_with = "number_or_none", skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+
+    /// IDs of the jobs that a queued job is waiting for.
+    #[serde(rename = "blockedBy", default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_by: Vec<String>,
+
+    /// Why a job failed (or was cancelled), e.g. that an uploaded file couldn't be parsed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+impl fmt::Display for JobStatusResponse {
+    /// Describes the status along with whatever details came with it, e.g. "Running (42% complete)"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.status)?;
+        if let Some(progress) = self.progress {
+            write!(f, " ({:.0}% complete)", progress)?;
+        }
+        if !self.blocked_by.is_empty() {
+            write!(f, " (waiting for job {})", self.blocked_by.join(", "))?;
+        }
+        if let Some(ref reason) = self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+fn number_or_none<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let value: Option<serde_json::Value> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(value.and_then(|v| v.as_f64()))
 }
 
 /// Things that can go wrong when making requests with the API.
+///
+/// The `Display` impl explains the problem in plain language, for showing to the user.
 #[derive(Debug)]
 pub enum ApiError {
     /// Covers communications errors. Problems with HTTPS (typically cert issues), problems with IO,
     /// problems where the server responded with JSON that this client doesn't know how to parse, etc.
     Protocol(reqwest::Error),
 
+    /// Generated when a `BranchSpec` doesn't identify exactly one branch, i.e. there are no branches
+    /// with the given name or ID, or there are several branches whose names match.
+    BranchResolution(String),
+
+    /// Generated when a project metadata field name given by the user isn't one of the server's fields.
+    UnknownMetadataField(String),
+
     /// Generated by `ApiClient::expect_success` when the response code was not 2xx.
     ///
     /// Additionally holds the error response, which will be an `ApiErrorMessage::Nice`
@@ -155,6 +475,95 @@ impl From<reqwest::Error> for ApiError {
         ApiError::Protocol(err)
     }
 }
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApiError::Protocol(ref e) if e.is_timeout() =>
+                write!(f, "The request to Code Dx timed out. The server may be busy or unreachable; \
+                    if it's just slow, try a longer --timeout (given before the command name)."),
+            ApiError::Protocol(ref e) if is_tls_error(e) =>
+                write!(f, "Couldn't make a secure connection to Code Dx: {}. If the server's certificate \
+                    comes from a private CA, use --ca-cert to trust it.", root_cause(e)),
+            ApiError::Protocol(ref e) if e.is_connect() =>
+                write!(f, "Couldn't connect to Code Dx: {}. Check the base url, and that the server is \
+                    running and reachable from here.", root_cause(e)),
+            ApiError::Protocol(ref e) if e.is_decode() =>
+                write!(f, "Couldn't understand the response from Code Dx: {}", root_cause(e)),
+            ApiError::Protocol(ref e) =>
+                write!(f, "Problem communicating with Code Dx: {}", root_cause(e)),
+            ApiError::BranchResolution(ref msg) => write!(f, "{}", msg),
+            ApiError::UnknownMetadataField(ref name) => write!(f, "there is no project metadata field named \"{}\"", name),
+            ApiError::NonSuccess(status, ref msg) => {
+                match status.as_u16() {
+                    401 => write!(f, "Code Dx didn't accept the credentials ({}). Check the username and \
+                        password, or the API key.", status)?,
+                    403 => write!(f, "The credentials don't have permission to do that ({})", status)?,
+                    404 => write!(f, "Code Dx couldn't find what was asked for ({}). Check that the \
+                        project, branch, analysis, finding, or job exists.", status)?,
+                    500..=599 => write!(f, "Code Dx had a problem handling the request ({})", status)?,
+                    _ => write!(f, "Code Dx responded with {}", status)?,
+                }
+                match msg.summary() {
+                    Some(summary) => write!(f, "\nCode Dx said: {}", summary),
+                    None => Ok(()),
+                }
+            },
+            ApiError::IO(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Broad categories of `ApiError`, for deciding how to react to one (e.g. what exit code to use).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApiErrorKind {
+    /// The credentials were wrong, or aren't allowed to do what was asked (401 and 403)
+    Auth,
+    /// Something the user asked for doesn't exist (404, or a failed lookup)
+    NotFound,
+    /// Couldn't reach the server at all, or timed out; includes TLS problems
+    Connection,
+    /// The server failed while handling the request (5xx)
+    Server,
+    Other,
+}
+impl ApiError {
+    pub fn kind(&self) -> ApiErrorKind {
+        match *self {
+            ApiError::Protocol(ref e) if e.is_connect() || e.is_timeout() || is_tls_error(e) => ApiErrorKind::Connection,
+            ApiError::BranchResolution(_) | ApiError::UnknownMetadataField(_) => ApiErrorKind::NotFound,
+            ApiError::NonSuccess(status, _) => match status.as_u16() {
+                401 | 403 => ApiErrorKind::Auth,
+                404 => ApiErrorKind::NotFound,
+                500..=599 => ApiErrorKind::Server,
+                _ => ApiErrorKind::Other,
+            },
+            _ => ApiErrorKind::Other,
+        }
+    }
+}
+
+/// The innermost error in `err`'s chain of sources, which usually has the most specific explanation.
+fn root_cause(err: &reqwest::Error) -> String {
+    let mut cause: &dyn Error = err;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause.to_string()
+}
+
+/// Whether the error came from TLS (e.g. an untrusted certificate) rather than the connection itself.
+///
+/// reqwest doesn't expose the TLS backend's errors directly, but they're somewhere in the chain of sources.
+fn is_tls_error(err: &reqwest::Error) -> bool {
+    let mut cause = err.source();
+    while let Some(e) = cause {
+        if e.is::<native_tls::Error>() {
+            return true;
+        }
+        cause = e.source();
+    }
+    false
+}
 
 #[derive(Debug)]
 pub enum ApiErrorMessage {
@@ -169,6 +578,25 @@ impl ApiErrorMessage {
                 .or_else(|_| Ok(ApiErrorMessage::Raw(body)))
         })
     }
+
+    /// A short version of the message for showing to the user, if there's anything worth showing.
+    ///
+    /// Raw messages are often whole HTML error pages, which aren't.
+    fn summary(&self) -> Option<String> {
+        match *self {
+            ApiErrorMessage::Nice(ref msg) => Some(msg.clone()),
+            ApiErrorMessage::Raw(ref body) => {
+                let body = body.trim();
+                if body.is_empty() || body.starts_with('<') {
+                    None
+                } else if body.chars().count() > 300 {
+                    Some(body.chars().take(300).collect::<String>() + "...")
+                } else {
+                    Some(body.to_string())
+                }
+            },
+        }
+    }
 }
 
 /// Represents the usual structure of error messages generated by Code Dx for expected errors.
@@ -200,6 +628,50 @@ impl <T: Debug> PollingStrategy<T> for Duration {
     }
 }
 
+/// Lets strategies be chosen at runtime, e.g. based on command line options.
+impl <T, P: PollingStrategy<T> + ?Sized> PollingStrategy<T> for Box<P> {
+    fn next_wait(&self, iteration_number: usize, state: &T) -> Option<Duration> {
+        self.as_ref().next_wait(iteration_number, state)
+    }
+}
+
+/// Polling strategy that waits `initial` after the first check, doubling the wait after each
+/// check after that, up to `max`. Unlike the `Duration` strategy, this one doesn't print anything.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+impl Backoff {
+    /// A "backoff" that always waits the same amount of time, silently.
+    pub fn fixed(interval: Duration) -> Backoff {
+        Backoff { initial: interval, max: interval }
+    }
+}
+impl <T> PollingStrategy<T> for Backoff {
+    fn next_wait(&self, iteration_number: usize, _state: &T) -> Option<Duration> {
+        let doublings = (iteration_number.max(1) - 1).min(16) as u32;
+        Some(self.initial.saturating_mul(1 << doublings).min(self.max))
+    }
+}
+
+/// Polling strategy that follows the `inner` strategy until the `deadline`, then gives up.
+///
+/// The last wait is cut short so that the final check happens right at the deadline.
+/// Since giving up ends the poll with the latest state, the caller should check that state
+/// to find out whether the poll actually finished.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline<P> {
+    pub inner: P,
+    pub deadline: Instant,
+}
+impl <T, P: PollingStrategy<T>> PollingStrategy<T> for Deadline<P> {
+    fn next_wait(&self, iteration_number: usize, state: &T) -> Option<Duration> {
+        let remaining = self.deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero())?;
+        self.inner.next_wait(iteration_number, state).map(|wait| wait.min(remaining))
+    }
+}
+
 pub type ApiResult<T> = Result<T, ApiError>;
 
 
@@ -250,13 +722,32 @@ pub struct ApiClient {
 
 impl ApiClient {
     pub fn new(config: Box<ClientConfig>) -> ApiClient {
-        let client_builder = reqwest::blocking::Client::builder();
-        // the --insecure CLI flag enables this, to disable TLS hostname verification
-        let client_builder = if config.allows_insecure() {
-            client_builder.danger_accept_invalid_hostnames(true)
-        } else {
-            client_builder
-        };
+        let mut client_builder = reqwest::blocking::Client::builder();
+        // the --insecure CLI flag enables this, to disable TLS certificate and hostname verification
+        if config.allows_insecure() {
+            client_builder = client_builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+        for cert in &config.ca_certs {
+            client_builder = client_builder.add_root_certificate(cert.clone());
+        }
+        if let Some(ref identity) = config.client_identity {
+            client_builder = client_builder.identity(identity.clone());
+        }
+        // an explicit proxy replaces the ones from environment variables, but should still respect NO_PROXY
+        if let Some(ref proxy_url) = config.proxy {
+            let proxy = reqwest::Proxy::all(proxy_url.clone())
+                .expect("proxy url was already validated")
+                .no_proxy(reqwest::NoProxy::from_env());
+            client_builder = client_builder.proxy(proxy);
+        }
+        client_builder = client_builder
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.timeout)
+            // without a total timeout, keepalive probes are what notice a connection that silently died
+            .tcp_keepalive(Duration::from_secs(60))
+            .user_agent(config.user_agent.as_str());
         let client = client_builder.build().unwrap();
         ApiClient { config, client }
     }
@@ -265,36 +756,43 @@ impl ApiClient {
         self.config.as_ref()
     }
 
-    pub fn get_job_status(&self, job_id: &str) -> ApiResult<JobStatus> {
+    pub fn get_job(&self, job_id: &str) -> ApiResult<JobStatusResponse> {
         self.api_get(&["api", "jobs", job_id])
             .expect_success()
-            .expect_json::<JobStatusResponse>()
-            .map(|jsr| jsr.status)
+            .expect_json()
+    }
+
+    /// Ask the server to stop a queued or running job.
+    pub fn cancel_job(&self, job_id: &str) -> ApiResult<()> {
+        self.api_delete(&["api", "jobs", job_id])
+            .expect_success()
+            .get()
+            .map(|_| ())
     }
 
-    /// Repeatedly call `get_job_status(job_id)` until it returns an error or a "ready" status.
+    /// Repeatedly call `get_job(job_id)` until it returns an error or a "ready" status.
     ///
     /// Uses the provided `polling_stategy` to determine how long to wait between each status
     /// check, and whether to abort early.
     ///
     /// If the `polling_strategy` decides to abort early, the result of the poll will be the
-    /// most recent `JobStatus` to be passed.
+    /// most recent `JobStatusResponse` to be passed.
     ///
-    /// If at any point the job status check fails (i.e. `get_job_status` returns an `Err(_)`),
+    /// If at any point the job status check fails (i.e. `get_job` returns an `Err(_)`),
     /// the poll will immediately stop, returning that error.
-    pub fn poll_job_completion<P: PollingStrategy<JobStatus>>(&self, job_id: &str, polling_strategy: P) -> ApiResult<JobStatus> {
+    pub fn poll_job_completion<P: PollingStrategy<JobStatusResponse>>(&self, job_id: &str, polling_strategy: P) -> ApiResult<JobStatusResponse> {
         let mut iteration_number: usize = 0;
         loop {
-            let status_result = self.get_job_status(job_id);
+            let status_result = self.get_job(job_id);
             iteration_number += 1;
             match status_result {
-                Ok(status) => {
-                    if status.is_ready() {
+                Ok(ref job) => {
+                    if job.status.is_ready() {
                         break status_result;
                     } else {
                         // call the "step" function to see if the poll should continue,
                         // and if so, how long it should wait before checking again
-                        match polling_strategy.next_wait(iteration_number, &status) {
+                        match polling_strategy.next_wait(iteration_number, job) {
                             Some(wait_dur) => thread::sleep(wait_dur),
                             None => break status_result,
                         }
@@ -312,32 +810,227 @@ impl ApiClient {
 
     }
 
+    /// Get the result of a completed job whose result is JSON, without assuming what kind of job it was.
+    pub fn get_job_result_json(&self, job_id: &str) -> ApiResult<serde_json::Value> {
+        self.api_get(&["api", "jobs", job_id, "result"])
+            .expect_success()
+            .expect_json()
+    }
+
+    /// Download the (non-JSON) result of a completed job, e.g. a generated report, to `destination`.
+    ///
+    /// The download goes to a `.part` file next to `destination`, which is only renamed once the
+    /// download finishes, so a failed download never leaves a truncated file at `destination`.
+    /// Returns the number of bytes written.
+    pub fn download_job_result(&self, job_id: &str, destination: &Path) -> ApiResult<u64> {
+        let mut partial = destination.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+
+        let mut response = self.api_get(&["api", "jobs", job_id, "result"])
+            .expect_success()
+            .get()?;
+        let result = File::create(&partial)
+            .map_err(ApiError::from)
+            .and_then(|mut file| response.copy_to(&mut file).map_err(ApiError::from))
+            .and_then(|num_bytes| fs::rename(&partial, destination).map(|_| num_bytes).map_err(ApiError::from));
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        result
+    }
+
+    /// Request a report for the given project context; the report is generated by a job,
+    /// whose result can be fetched with `download_job_result` once it completes.
+    pub fn generate_report(&self, project_context: &ProjectContext, filter: &ApiFindingFilter, config: &ApiReportConfig) -> ApiResult<ApiJobResponse> {
+        self.api_post(&["api", "projects", &project_context.api_string, "report", config.report_type()], json!({ "filter": filter, "config": config }))
+            .expect_success()
+            .expect_json()
+    }
+
     pub fn get_projects(&self) -> ApiResult<Vec<ApiProject>> {
         self.api_get(&["x", "projects"])
             .expect_success()
             .expect_json()
     }
 
-    pub fn query_projects<'a>(&self, filter: &'a ApiProjectFilter) -> ApiResult<Vec<ApiProject>> {
+    pub fn query_projects(&self, filter: &ApiProjectFilter) -> ApiResult<Vec<ApiProject>> {
         self.api_post(&["x", "projects", "query"], json!({ "filter": filter }))
             .expect_success()
             .expect_json()
     }
 
+    pub fn create_project(&self, name: &str, parent_id: Option<u32>) -> ApiResult<ApiProject> {
+        self.api_post(&["api", "projects"], json!({ "name": name, "parentId": parent_id }))
+            .expect_success()
+            .expect_json()
+    }
+
+    pub fn rename_project(&self, project_id: u32, name: &str) -> ApiResult<()> {
+        self.api_put(&["api", "projects", &project_id.to_string()], json!({ "name": name }))
+            .expect_success()
+            .get()
+            .map(|_| ())
+    }
+
+    /// Move a project into the group of the given `parent_id`, or to the top level if `parent_id` is `None`.
+    pub fn move_project(&self, project_id: u32, parent_id: Option<u32>) -> ApiResult<()> {
+        self.api_put(&["api", "projects", &project_id.to_string()], json!({ "parentId": parent_id }))
+            .expect_success()
+            .get()
+            .map(|_| ())
+    }
+
+    pub fn delete_project(&self, project_id: u32) -> ApiResult<()> {
+        self.api_delete(&["api", "projects", &project_id.to_string()])
+            .expect_success()
+            .get()
+            .map(|_| ())
+    }
+
+    pub fn get_metadata_fields(&self) -> ApiResult<Vec<ApiMetadataField>> {
+        self.api_get(&["x", "project-metadata", "fields"])
+            .expect_success()
+            .expect_json()
+    }
+
+    /// Get a project's metadata values, keyed by field name.
+    ///
+    /// Fields that have no value for the project will be present, with a `null` value.
+    pub fn get_project_metadata(&self, project_id: u32) -> ApiResult<BTreeMap<String, serde_json::Value>> {
+        let fields = self.get_metadata_fields()?;
+        let values_by_id: HashMap<String, serde_json::Value> = self.api_get(&["x", "projects", &project_id.to_string(), "metadata"])
+            .expect_success()
+            .expect_json()?;
+        Ok(fields.into_iter().map(|field| {
+            let value = values_by_id.get(&field.id).cloned().unwrap_or(serde_json::Value::Null);
+            (field.name, value)
+        }).collect())
+    }
+
+    /// Set some of a project's metadata values. The `values` are keyed by (case-insensitive) field name;
+    /// fields that aren't mentioned keep their current value.
+    pub fn set_project_metadata(&self, project_id: u32, values: &HashMap<&str, &str>) -> ApiResult<()> {
+        let fields = self.get_metadata_fields()?;
+        let mut values_by_id = HashMap::new();
+        for (name, value) in values {
+            let field = fields.iter()
+                .find(|field| field.name.to_lowercase() == name.to_lowercase())
+                .ok_or_else(|| ApiError::UnknownMetadataField(name.to_string()))?;
+            values_by_id.insert(field.id.as_str(), *value);
+        }
+        self.api_put(&["x", "projects", &project_id.to_string(), "metadata"], json!(values_by_id))
+            .expect_success()
+            .get()
+            .map(|_| ())
+    }
+
     pub fn get_branches_for_project(&self, project_id: u32) -> ApiResult<Vec<ApiBranch>> {
         self.api_get(&["x", "projects", &project_id.to_string(), "branches"])
             .expect_success()
             .expect_json()
     }
 
-    pub fn query_branches_for_project(&self, project_id: u32, branch_name: &str) -> ApiResult<Vec<ApiBranch>> {
-        let branch_name_lowercase = branch_name.to_lowercase();
-        self.get_branches_for_project(project_id).map(|branches| {
-            branches
-                .into_iter()
-                .filter(|branch| branch.name.to_lowercase().contains(&branch_name_lowercase))
-                .collect()
-        })
+    /// Look up a single branch by ID, or `None` if the project has no such branch.
+    pub fn get_branch(&self, project_id: u32, branch_id: u32) -> ApiResult<Option<ApiBranch>> {
+        let result = self.api_get(&["x", "projects", &project_id.to_string(), "branches", &branch_id.to_string()])
+            .expect_success()
+            .expect_json::<ApiBranch>();
+        match result {
+            Ok(branch) if branch.project_id == project_id => Ok(Some(branch)),
+            Ok(_) => Ok(None),
+            Err(ApiError::NonSuccess(status, _)) if status == reqwest::StatusCode::NOT_FOUND => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Create a new branch named `name`, off of the existing `parent_branch_id` branch.
+    pub fn create_branch(&self, project_id: u32, name: &str, parent_branch_id: u32) -> ApiResult<ApiBranch> {
+        self.api_post(&["x", "projects", &project_id.to_string(), "branches"], json!({ "name": name, "parentId": parent_branch_id }))
+            .expect_success()
+            .expect_json()
+    }
+
+    pub fn delete_branch(&self, project_id: u32, branch_id: u32) -> ApiResult<()> {
+        self.api_delete(&["x", "projects", &project_id.to_string(), "branches", &branch_id.to_string()])
+            .expect_success()
+            .get()
+            .map(|_| ())
+    }
+
+    pub fn set_default_branch(&self, project_id: u32, branch_id: u32) -> ApiResult<()> {
+        self.api_put(&["x", "projects", &project_id.to_string(), "branches", &branch_id.to_string()], json!({ "isDefault": true }))
+            .expect_success()
+            .get()
+            .map(|_| ())
+    }
+
+    pub fn query_branches_for_project(&self, filter: &ApiBranchFilter) -> ApiResult<Vec<ApiBranch>> {
+        self.api_post(&["x", "projects", &filter.project_id.to_string(), "branches", "query"], json!({ "filter": filter }))
+            .expect_success()
+            .expect_json()
+    }
+
+    pub fn query_findings(&self, project_context: &ProjectContext, filter: &ApiFindingFilter, pagination: ApiPagination) -> ApiResult<Vec<ApiFinding>> {
+        self.api_post(&["api", "projects", &project_context.api_string, "findings", "query"], json!({ "filter": filter, "pagination": pagination }))
+            .expect_success()
+            .expect_json()
+    }
+
+    /// Run `query_findings` one page at a time until every matching finding has been collected.
+    pub fn query_all_findings(&self, project_context: &ProjectContext, filter: &ApiFindingFilter) -> ApiResult<Vec<ApiFinding>> {
+        let per_page = 500;
+        let mut findings = Vec::new();
+        let mut page = 1;
+        loop {
+            let batch = self.query_findings(project_context, filter, ApiPagination { page, per_page })?;
+            let is_last_page = batch.len() < per_page as usize;
+            findings.extend(batch);
+            if is_last_page {
+                break Ok(findings);
+            }
+            page += 1;
+        }
+    }
+
+    pub fn count_findings(&self, project_context: &ProjectContext, filter: &ApiFindingFilter) -> ApiResult<u64> {
+        self.api_post(&["api", "projects", &project_context.api_string, "findings", "count"], json!({ "filter": filter }))
+            .expect_success()
+            .expect_json::<ApiCountResponse>()
+            .map(|resp| resp.count)
+    }
+
+    pub fn get_finding(&self, finding_id: u32) -> ApiResult<ApiFinding> {
+        self.api_get(&["api", "findings", &finding_id.to_string()])
+            .expect_success()
+            .expect_json()
+    }
+
+    pub fn add_finding_comment(&self, finding_id: u32, content: &str) -> ApiResult<ApiFindingComment> {
+        self.api_post(&["api", "findings", &finding_id.to_string(), "comments"], json!({ "content": content }))
+            .expect_success()
+            .expect_json()
+    }
+
+    /// Get everything that has happened to a finding, oldest first.
+    pub fn get_finding_history(&self, finding_id: u32) -> ApiResult<Vec<ApiFindingHistoryEntry>> {
+        self.api_get(&["api", "findings", &finding_id.to_string(), "history"])
+            .expect_success()
+            .expect_json()
+    }
+
+    /// Change the status of every finding matching the `filter`. The change is made by a job,
+    /// which should be polled to find out when it's done.
+    pub fn update_findings_status(&self, project_context: &ProjectContext, filter: &ApiFindingFilter, update: &ApiStatusUpdate) -> ApiResult<ApiJobResponse> {
+        #[derive(Serialize)]
+        struct Body<'b> {
+            filter: &'b ApiFindingFilter<'b>,
+            #[serde(flatten)]
+            update: &'b ApiStatusUpdate<'b>,
+        }
+        self.api_post(&["api", "projects", &project_context.api_string, "bulk-status-update"], ReqBody::as_json(Body { filter, update }))
+            .expect_success()
+            .expect_json()
     }
 
     pub fn start_analysis(&self, project_context: ProjectContext, branch_name: Option<String>, files: Vec<&Path>) -> ApiResult<ApiAnalysisJobResponse> {
@@ -345,21 +1038,16 @@ impl ApiClient {
         let form = files
             .iter()
             .enumerate()
-            .fold(Ok(reqwest::blocking::multipart::Form::new()),  move |maybe_form, (index, file)| {
-                maybe_form.and_then(|mut form| {
-                    if !branch_name_string.is_empty() {
-                        form = form.text("branchName", branch_name_string.clone())
-                    }
-                    form.file(format!("file{}", index), file)
-                })
-            })
-            .map_err(ApiError::from);
+            .fold(MultipartForm::new(), move |mut form, (index, file)| {
+                if !branch_name_string.is_empty() {
+                    form = form.text("branchName", branch_name_string.clone())
+                }
+                form.file(format!("file{}", index), file)
+            });
 
-        form.and_then(|form| {
-            self.api_post(&["api", "projects", &project_context.api_string, "analysis"], form)
-                .expect_success()
-                .expect_json::<ApiAnalysisJobResponse>()
-        })
+        self.api_post(&["api", "projects", &project_context.api_string, "analysis"], form)
+            .expect_success()
+            .expect_json::<ApiAnalysisJobResponse>()
     }
 
     pub fn start_analysis_with_git(&self, project_context: ProjectContext, branch_name: Option<String>, include_git_source: bool, git_branch_name: Option<String>, files: Vec<&Path>) -> ApiResult<ApiAnalysisWithGitSourceJobResponse> {
@@ -369,25 +1057,47 @@ impl ApiClient {
         let form = files
             .iter()
             .enumerate()
-            .fold(Ok(reqwest::blocking::multipart::Form::new()), |maybe_form, (index, file)| {
-                maybe_form.and_then(|mut form| {
-                    if !branch_name_string.is_empty() {
-                        form = form.text("branchName", branch_name_string.clone())
-                    }
-                    if !git_branch_name_string.is_empty() {
-                        form = form.text("gitBranchName", git_branch_name_string.clone())
-                    }
-                    form = form.text("includeGitSource", include_git_source.to_string());
-                    form.file(format!("file{}", index), file)
-                })
-            })
-            .map_err(ApiError::from);
+            .fold(MultipartForm::new(), |mut form, (index, file)| {
+                if !branch_name_string.is_empty() {
+                    form = form.text("branchName", branch_name_string.clone())
+                }
+                if !git_branch_name_string.is_empty() {
+                    form = form.text("gitBranchName", git_branch_name_string.clone())
+                }
+                form = form.text("includeGitSource", include_git_source.to_string());
+                form.file(format!("file{}", index), file)
+            });
 
-        form.and_then(|form| {
-            self.api_post(&["api", "projects", &project_context.project_id.to_string(), "analysis"], form)
-                .expect_success()
-                .expect_json::<ApiAnalysisWithGitSourceJobResponse>()
-        })
+        self.api_post(&["api", "projects", &project_context.project_id.to_string(), "analysis"], form)
+            .expect_success()
+            .expect_json::<ApiAnalysisWithGitSourceJobResponse>()
+    }
+
+    pub fn get_analyses(&self, project_id: u32) -> ApiResult<Vec<ApiAnalysis>> {
+        self.api_get(&["x", "projects", &project_id.to_string(), "analyses"])
+            .expect_success()
+            .expect_json()
+    }
+
+    pub fn get_analysis(&self, project_id: u32, analysis_id: u32) -> ApiResult<ApiAnalysis> {
+        self.api_get(&["x", "projects", &project_id.to_string(), "analyses", &analysis_id.to_string()])
+            .expect_success()
+            .expect_json()
+    }
+
+    /// Find an analysis by ID alone, by looking for it in each project in turn, or `None` if no project has it.
+    ///
+    /// Projects that turn out to be off limits (a 403) are skipped, since the analysis is most likely elsewhere.
+    pub fn find_analysis(&self, analysis_id: u32) -> ApiResult<Option<ApiAnalysis>> {
+        for project in self.get_projects()? {
+            match self.get_analysis(project.id, analysis_id) {
+                Ok(analysis) => return Ok(Some(analysis)),
+                Err(ApiError::NonSuccess(status, _))
+                    if status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::FORBIDDEN => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
     }
 
     pub fn set_analysis_name(&self, project_context: ProjectContext, analysis_id: u32, name: &str) -> ApiResult<()> {
@@ -413,35 +1123,117 @@ impl ApiClient {
         self.api_request(Method::PUT, path_segments, body)
     }
 
+    pub fn api_delete(&self, path_segments: &[&str]) -> ApiResponse {
+        self.api_request(Method::DELETE, path_segments, ReqBody::None)
+    }
+
+    /// Send a request to the Code Dx API.
+    ///
+    /// Requests that fail for reasons that are likely to be temporary (connection problems, or a
+    /// 429, 502, 503, or 504 response) are retried according to the config's `RetryPolicy`.
     pub fn api_request<B>(&self, method: Method, path_segments: &[&str], body: B) -> ApiResponse
         where B: Into<ReqBody>
     {
         let url = self.config.api_url(path_segments);
+        let body = body.into();
+        let mut num_failures = 0;
+        loop {
+            let result = self.send_request(method.clone(), url.clone(), &body);
+            let retry_reason = match result {
+                Ok(ref response) => retryable_status(&method, response),
+                Err(ApiError::Protocol(ref e)) => retryable_error(&method, e),
+                Err(_) => None,
+            };
+            let delay = retry_reason.as_ref().and_then(|_| {
+                num_failures += 1;
+                let retry_after = result.as_ref().ok().and_then(retry_after);
+                self.config.retry.delay(num_failures, retry_after)
+            });
+            match (retry_reason, delay) {
+                (Some(reason), Some(delay)) => {
+                    eprintln!("# {}; retrying in {:.1}s (retry {} of {})", reason, delay.as_secs_f64(), num_failures, self.config.retry.max_retries);
+                    thread::sleep(delay);
+                },
+                _ => return ApiResponse::from(result),
+            }
+        }
+    }
+
+    fn send_request(&self, method: Method, url: reqwest::Url, body: &ReqBody) -> ApiResult<reqwest::blocking::Response> {
         let request_builder = self.client.request(method, url);
         let configured_rb = self.config.apply_auth(request_builder);
-        match body.into() {
-            ReqBody::Json(ref json) => {
-                ApiResponse::from(configured_rb.json(json).send().map_err(ApiError::from))
-            },
-            ReqBody::Form(form) => {
-                ApiResponse::from(configured_rb.multipart(form).send().map_err(ApiError::from))
-            },
-            ReqBody::None => ApiResponse::from(configured_rb.send().map_err(ApiError::from))
-        }
+        let configured_rb = match *body {
+            ReqBody::Json(ref json) => configured_rb.json(json),
+            // the form has to be rebuilt for each attempt, since sending it consumes it
+            ReqBody::Form(ref form) => configured_rb.multipart(form.build()?),
+            ReqBody::None => configured_rb,
+        };
+        configured_rb.send().map_err(ApiError::from)
+    }
+}
+
+/// If the response's status means the request might work if tried again, describe what went wrong.
+///
+/// `429` and `503` mean the request was turned away, so it's always safe to try again. A `502` or `504`
+/// may come from a proxy after Code Dx already got the request, so those are only retried for methods
+/// that are safe to repeat, like in `retryable_error`.
+fn retryable_status(method: &Method, response: &reqwest::blocking::Response) -> Option<String> {
+    match response.status().as_u16() {
+        429 | 503 => Some(format!("Code Dx responded with {}", response.status())),
+        502 | 504 if is_idempotent(method) => Some(format!("Code Dx responded with {}", response.status())),
+        _ => None,
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::PUT | Method::DELETE | Method::HEAD)
+}
+
+/// If the error means the request might work if tried again, describe what went wrong.
+///
+/// Connection failures are always retried, since the request never reached the server.
+/// Other failures (e.g. the connection dropping partway through) are only retried for methods
+/// that are safe to repeat, so that e.g. an analysis isn't started twice.
+fn retryable_error(method: &Method, error: &reqwest::Error) -> Option<String> {
+    let idempotent = is_idempotent(method);
+    if error.is_connect() {
+        Some("Couldn't connect to Code Dx".to_string())
+    } else if idempotent && error.is_timeout() {
+        Some("The request to Code Dx timed out".to_string())
+    } else if idempotent && error.is_request() {
+        Some("The connection to Code Dx was interrupted".to_string())
+    } else {
+        None
+    }
+}
+
+/// Read the response's `Retry-After` header, which may be a number of seconds or an HTTP date.
+fn retry_after(response: &reqwest::blocking::Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, SystemTime::now())
+}
+
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            Some(date.duration_since(now).unwrap_or_default())
+        },
     }
 }
 
 /// Collection of types that `ApiClient` knows how to use as a request body.
 pub enum ReqBody {
     /// A multipart form, typically used for file uploads.
-    Form(reqwest::blocking::multipart::Form),
+    Form(MultipartForm),
     /// A JSON object as the body
     Json(serde_json::Value),
     /// No body
     None,
 }
 impl ReqBody {
-    #[allow(dead_code)]
     pub fn as_json<T: Serialize>(body: T) -> ReqBody {
         ReqBody::Json(serde_json::to_value(body).unwrap())
     }
@@ -451,8 +1243,109 @@ impl From<serde_json::Value> for ReqBody {
         ReqBody::Json(json)
     }
 }
-impl From<reqwest::blocking::multipart::Form> for ReqBody {
-    fn from(form: reqwest::blocking::multipart::Form) -> ReqBody {
+impl From<MultipartForm> for ReqBody {
+    fn from(form: MultipartForm) -> ReqBody {
         ReqBody::Form(form)
     }
-}
\ No newline at end of file
+}
+
+/// Description of a multipart form body, which can be turned into a reqwest `Form` as many times as needed.
+///
+/// A reqwest `Form` can only be sent once, since it reads its files as it's sent,
+/// so retrying a request needs a fresh one each time.
+#[derive(Debug, Clone, Default)]
+pub struct MultipartForm {
+    parts: Vec<(String, FormPart)>,
+}
+#[derive(Debug, Clone)]
+enum FormPart {
+    Text(String),
+    File(PathBuf),
+}
+impl MultipartForm {
+    pub fn new() -> MultipartForm {
+        MultipartForm::default()
+    }
+
+    pub fn text<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> MultipartForm {
+        self.parts.push((name.into(), FormPart::Text(value.into())));
+        self
+    }
+
+    pub fn file<N: Into<String>>(mut self, name: N, path: &Path) -> MultipartForm {
+        self.parts.push((name.into(), FormPart::File(path.to_owned())));
+        self
+    }
+
+    /// Build the reqwest `Form`, opening each of the files.
+    fn build(&self) -> io::Result<reqwest::blocking::multipart::Form> {
+        self.parts.iter().try_fold(reqwest::blocking::multipart::Form::new(), |form, (name, part)| {
+            match *part {
+                FormPart::Text(ref value) => Ok(form.text(name.clone(), value.clone())),
+                FormPart::File(ref path) => form.file(name.clone(), path),
+            }
+        })
+    }
+}
+
+#[test]
+fn test_parse_retry_after(){
+    let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+    assert!(parse_retry_after("120", now) == Some(Duration::from_secs(120)));
+    assert!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now) == Some(Duration::from_secs(30)));
+    assert!(parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now) == Some(Duration::from_secs(0)));
+    assert!(parse_retry_after("soon", now).is_none());
+}
+
+#[test]
+fn test_error_kinds_and_messages(){
+    let unauthorized = ApiError::NonSuccess(reqwest::StatusCode::UNAUTHORIZED, ApiErrorMessage::Nice("bad key".to_string()));
+    assert!(unauthorized.kind() == ApiErrorKind::Auth);
+    assert!(unauthorized.to_string().ends_with("\nCode Dx said: bad key"));
+
+    let not_found = ApiError::NonSuccess(reqwest::StatusCode::NOT_FOUND, ApiErrorMessage::Raw("<html>nope</html>".to_string()));
+    assert!(not_found.kind() == ApiErrorKind::NotFound);
+    assert!(!not_found.to_string().contains("html"));
+
+    let internal = ApiError::NonSuccess(reqwest::StatusCode::BAD_GATEWAY, ApiErrorMessage::Raw(String::new()));
+    assert!(internal.kind() == ApiErrorKind::Server);
+    assert!(ApiError::BranchResolution("no branch".to_string()).kind() == ApiErrorKind::NotFound);
+    assert!(ApiError::UnknownMetadataField("Owner".to_string()).kind() == ApiErrorKind::NotFound);
+}
+
+#[test]
+fn test_backoff_polling(){
+    let backoff = Backoff { initial: Duration::from_secs(2), max: Duration::from_secs(30) };
+    let waits: Vec<u64> = (1..=6).map(|n| backoff.next_wait(n, &()).unwrap().as_secs()).collect();
+    assert!(waits == vec![2, 4, 8, 16, 30, 30]);
+    assert!(Backoff::fixed(Duration::from_secs(5)).next_wait(10, &()) == Some(Duration::from_secs(5)));
+}
+
+#[test]
+fn test_deadline_polling(){
+    let inner = Backoff::fixed(Duration::from_secs(5));
+    let passed = Deadline { inner, deadline: Instant::now() };
+    assert!(passed.next_wait(1, &()).is_none());
+
+    let soon = Deadline { inner, deadline: Instant::now() + Duration::from_secs(2) };
+    assert!(soon.next_wait(1, &()).unwrap() <= Duration::from_secs(2));
+
+    let later = Deadline { inner, deadline: Instant::now() + Duration::from_secs(60) };
+    assert!(later.next_wait(1, &()) == Some(Duration::from_secs(5)));
+}
+
+#[test]
+fn test_job_status_details(){
+    let parse = |json: &str| serde_json::from_str::<JobStatusResponse>(json).unwrap();
+
+    let running = parse(r#"{"jobId":"a","status":"running","progress":42.4}"#);
+    let blocked = parse(r#"{"jobId":"a","status":"queued","blockedBy":["b","c"]}"#);
+    let failed = parse(r#"{"jobId":"a","status":"failed","reason":"The file was unparseable"}"#);
+    let odd_progress = parse(r#"{"jobId":"a","status":"running","progress":{"current":1}}"#);
+
+    assert!(running.to_string() == "Running (42% complete)");
+    assert!(blocked.to_string() == "Queued (waiting for job b, c)");
+    assert!(failed.to_string() == "Failed: The file was unparseable");
+    assert!(odd_progress.progress.is_none() && odd_progress.to_string() == "Running");
+    assert!(serde_json::to_string(&failed).unwrap() == r#"{"jobId":"a","status":"failed","reason":"The file was unparseable"}"#);
+}
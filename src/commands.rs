@@ -15,26 +15,71 @@
  */
 
 use crate::branching::*;
-use clap::{ArgMatches, App, Arg, SubCommand};
+use clap::{ArgMatches, App, AppSettings, Arg, SubCommand};
 use crate::client::*;
-use serde_json;
+use crate::credentials::CredentialStore;
+use crate::diff::{self, Change};
+use crate::inputs::AnalysisInputs;
+use crate::policy::FailCondition;
+use crate::sarif;
+use reqwest::Url;
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::io::{self, Write};
 use std::path::Path;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// A vector containing all of the runnable commands in this module.
 pub fn all<'a>() -> Vec<Box<dyn Command<'a>>> {
     vec![
         Box::new(ExitCommand),
+        Box::new(LoginCommand),
+        Box::new(LogoutCommand),
         Box::new(AnalyzeCommand),
+        Box::new(AnalysesCommand),
         Box::new(ProjectsCommand),
+        Box::new(ProjectCommand),
+        Box::new(MetadataCommand),
         Box::new(BranchesCommand),
+        Box::new(FindingsCommand),
+        Box::new(TriageCommand),
+        Box::new(FindingCommand),
+        Box::new(DiffCommand),
+        Box::new(ReportCommand),
+        Box::new(JobCommand),
     ]
 }
 
 /// Represents the intention to exit the application with a specific exit code.
 pub struct Exit(pub i32);
 
+/// Exit code for errors that don't fit in one of the more specific categories below.
+pub const EXIT_ERROR: i32 = 1;
+
+/// Exit code used when the connection settings or credentials couldn't be worked out (see `ConfigError`).
+pub const EXIT_CONFIG_ERROR: i32 = 2;
+
+/// Exit code used when an analysis (or a job waited on by `job wait`) finishes without completing successfully, e.g. it was `Failed`.
+pub const EXIT_ANALYSIS_FAILED: i32 = 3;
+
+/// Exit code used by `analyze --fail-on` when the analysis results meet one of the given conditions.
+pub const EXIT_POLICY_VIOLATION: i32 = 4;
+
+/// Exit code used when Code Dx rejects the credentials, or they don't allow what was asked (`ApiErrorKind::Auth`).
+pub const EXIT_AUTH_FAILED: i32 = 5;
+
+/// Exit code used when a project, branch, etc. doesn't exist (`ApiErrorKind::NotFound`).
+pub const EXIT_NOT_FOUND: i32 = 6;
+
+/// Exit code used when Code Dx couldn't be reached, e.g. it's down or the TLS handshake failed (`ApiErrorKind::Connection`).
+pub const EXIT_CONNECTION_FAILED: i32 = 7;
+
+/// Exit code used when Code Dx had an internal error while handling a request (`ApiErrorKind::Server`).
+pub const EXIT_SERVER_ERROR: i32 = 8;
+
+/// Exit code used by `analyze --wait-timeout` and `job wait --wait-timeout` when the job is still going when the time runs out.
+pub const EXIT_TIMED_OUT: i32 = 9;
+
 /// The result of running a command; either continue, or exit the application.
 pub type CommandResult = Result<(), Exit>;
 
@@ -108,6 +153,36 @@ impl <'a, T, A> Command<'a> for T where T: CommandInner<'a, Args = A> {
     }
 }
 
+/// Print an error that happened while `doing` something, and pick the exit code for that kind of error.
+fn api_failure(doing: &str, error: &ApiError) -> Exit {
+    eprintln!("Error {}: {}", doing, error);
+    Exit(match error.kind() {
+        ApiErrorKind::Auth => EXIT_AUTH_FAILED,
+        ApiErrorKind::NotFound => EXIT_NOT_FOUND,
+        ApiErrorKind::Connection => EXIT_CONNECTION_FAILED,
+        ApiErrorKind::Server => EXIT_SERVER_ERROR,
+        ApiErrorKind::Other => EXIT_ERROR,
+    })
+}
+
+/// Resolve the branch of a `ProjectContext` (see `ProjectContext::resolve`), printing an error if that fails.
+fn resolve_context(client: &ApiClient, project_context: &ProjectContext) -> Result<ProjectContext, Exit> {
+    project_context.resolve(client).map_err(|e| {
+        api_failure(&format!("resolving project context \"{}\"", project_context.api_string), &e)
+    })
+}
+
+/// Ask the user a yes/no `question` on the terminal, taking anything but "y" or "yes" as a no.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        Err(_) => false,
+    }
+}
+
 // -------------------------------------------------------------------------------------------------
 // ABOVE THIS POINT: command traits and supporting structs
 // -
@@ -129,7 +204,7 @@ impl <'a> CommandInner<'a> for ExitCommand {
     }
 
     fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
-        if let Some(_) = matches.subcommand_matches("exit") {
+        if matches.subcommand_matches("exit").is_some() {
             Some(Ok(()))
         } else {
             None
@@ -145,17 +220,275 @@ impl <'a> CommandInner<'a> for ExitCommand {
 }
 
 
+// -------------------------------------------------------------------------------------------------
+// COMMAND: login
+// -------------------------------------------------------------------------------------------------
+pub struct LoginCommand;
+impl <'a> CommandInner<'a> for LoginCommand {
+    type Args = ();
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("login")
+            .about("Save the current credentials, to be used automatically for this base url")
+            .after_help("The credentials are checked against Code Dx before they're saved. \
+            Later runs with the same base url and no other credentials will use them. \
+            See 'logout' to forget them.")
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("login").map(|_| Ok(()))
+    }
+
+    fn run(&self, client: &ApiClient, _args: Self::Args) -> CommandResult {
+        let config = client.get_config();
+        let store = CredentialStore::default_location().ok_or_else(|| {
+            eprintln!("Couldn't find a directory to store credentials in; set XDG_CONFIG_HOME or HOME");
+            Exit(EXIT_ERROR)
+        })?;
+
+        // make sure the credentials actually work before saving them
+        if let Err(e) = client.get_projects() {
+            return Err(api_failure("checking credentials", &e));
+        }
+
+        match store.put(&config.base_url, &config.auth_info) {
+            Ok(()) => {
+                println!("# Saved credentials for {}", config.base_url);
+                Ok(())
+            },
+            Err(e) => {
+                eprintln!("Error saving credentials: {}", e);
+                Err(Exit(EXIT_ERROR))
+            },
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: logout
+// -------------------------------------------------------------------------------------------------
+pub struct LogoutCommand;
+impl <'a> CommandInner<'a> for LogoutCommand {
+    type Args = ();
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("logout")
+            .about("Forget the credentials saved by 'login' for this base url")
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("logout").map(|_| Ok(()))
+    }
+
+    fn run(&self, client: &ApiClient, _args: Self::Args) -> CommandResult {
+        logout(&client.get_config().base_url)
+    }
+}
+
+/// Forget the saved credentials for `base_url`.
+///
+/// This doesn't need an `ApiClient`, so that `logout` can still be run from the command line when
+/// there are no usable credentials, e.g. because the saved ones are unreadable.
+pub fn logout(base_url: &Url) -> CommandResult {
+    let removed = match CredentialStore::default_location() {
+        Some(store) => store.remove(base_url),
+        None => Ok(false),
+    };
+    match removed {
+        Ok(true) => {
+            println!("# Removed saved credentials for {}", base_url);
+            Ok(())
+        },
+        Ok(false) => {
+            println!("# There were no saved credentials for {}", base_url);
+            Ok(())
+        },
+        Err(e) => {
+            eprintln!("Error removing saved credentials: {}", e);
+            Err(Exit(EXIT_ERROR))
+        },
+    }
+}
+
 // -------------------------------------------------------------------------------------------------
 // COMMAND: analyze
 // -------------------------------------------------------------------------------------------------
+
+/// Something that happened while running an analysis, reported according to a `ProgressFormat`.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+enum AnalyzeEvent<'a> {
+    /// A directory was archived into a zip file, to be uploaded in its place.
+    #[serde(rename_all = "camelCase")]
+    InputArchived { directory: &'a Path, file_count: usize },
+    /// A job was requested to prepare an analysis that includes git source; the analysis itself will start once that job finishes.
+    #[serde(rename_all = "camelCase")]
+    AnalysisRequested { job_id: &'a str },
+    #[serde(rename_all = "camelCase")]
+    AnalysisStarted { analysis_id: u32, job_id: &'a str, git_source: bool },
+    #[serde(rename_all = "camelCase")]
+    AnalysisNamed { analysis_id: u32, name: &'a str },
+    /// A job was checked on; the `job` fields (`jobId`, `status`, `progress`, etc) are included as-is.
+    #[serde(rename_all = "camelCase")]
+    StatusChanged { #[serde(flatten)] job: &'a JobStatusResponse, iteration: usize },
+    #[serde(rename_all = "camelCase")]
+    Finished { analysis_id: u32, #[serde(flatten)] job: &'a JobStatusResponse },
+    /// Gave up waiting for a job because of `--wait-timeout`. The analysis id is missing if the job
+    /// was the one preparing an analysis with git source, since the analysis hadn't started yet.
+    #[serde(rename_all = "camelCase")]
+    TimedOut {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        analysis_id: Option<u32>,
+        #[serde(flatten)]
+        job: &'a JobStatusResponse,
+        timeout_seconds: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    FailConditionMet { condition: String, matching_findings: usize },
+}
+
+/// How a long-running command like `analyze` reports its progress on STDOUT.
+#[derive(Clone, Copy, PartialEq)]
+enum ProgressFormat {
+    /// Human-readable comment lines starting with `#`
+    Text,
+    /// One JSON object per line, per `AnalyzeEvent`
+    Json,
+}
+impl ProgressFormat {
+    fn report(&self, event: AnalyzeEvent) {
+        match *self {
+            ProgressFormat::Json => println!("{}", serde_json::to_string(&event).unwrap()),
+            ProgressFormat::Text => match event {
+                AnalyzeEvent::InputArchived { directory, file_count } =>
+                    println!("# Archived {} file(s) from {}", file_count, directory.display()),
+                AnalyzeEvent::AnalysisRequested { job_id } =>
+                    println!("# Requesting new analysis with job id {} with included git source", job_id),
+                AnalyzeEvent::AnalysisStarted { analysis_id, job_id, git_source: true } =>
+                    println!("# Started analysis {} with job id {} with included git source", analysis_id, job_id),
+                AnalyzeEvent::AnalysisStarted { analysis_id, job_id, git_source: false } =>
+                    println!("# Started analysis {} with job id {}", analysis_id, job_id),
+                AnalyzeEvent::AnalysisNamed { analysis_id, name } =>
+                    println!("# Set analysis {}'s name to \"{}\"", analysis_id, name),
+                AnalyzeEvent::StatusChanged { job, iteration } =>
+                    println!("# Polling job completion, iteration {}: status = {}", iteration, job),
+                AnalyzeEvent::Finished { job, .. } => {
+                    println!("# Polling done");
+                    println!("{}", job);
+                },
+                AnalyzeEvent::TimedOut { job, timeout_seconds, .. } =>
+                    eprintln!("Gave up waiting for job {} after {} seconds; its status is {}", job.job_id, timeout_seconds, job),
+                AnalyzeEvent::FailConditionMet { condition, matching_findings } =>
+                    eprintln!("Fail condition met: {} ({} matching findings)", condition, matching_findings),
+            },
+        }
+    }
+
+    /// A polling strategy that waits according to `waits`, reporting the job's status as it goes
+    /// unless `quiet` is set.
+    ///
+    /// The text format reports every check, while the JSON format only reports changes in status or progress.
+    fn polling<P: PollingStrategy<JobStatusResponse>>(&self, waits: P, quiet: bool) -> ProgressPolling<P> {
+        ProgressPolling { format: *self, waits, quiet, last_status: Cell::new(None) }
+    }
+}
+
+/// Polling strategy created by `ProgressFormat::polling`.
+struct ProgressPolling<P> {
+    format: ProgressFormat,
+    waits: P,
+    quiet: bool,
+    last_status: Cell<Option<(JobStatus, Option<f64>)>>,
+}
+impl <P: PollingStrategy<JobStatusResponse>> PollingStrategy<JobStatusResponse> for ProgressPolling<P> {
+    fn next_wait(&self, iteration_number: usize, state: &JobStatusResponse) -> Option<Duration> {
+        let status = Some((state.status, state.progress));
+        if !self.quiet && (self.format == ProgressFormat::Text || self.last_status.get() != status) {
+            self.format.report(AnalyzeEvent::StatusChanged { iteration: iteration_number, job: state });
+        }
+        self.last_status.set(status);
+        self.waits.next_wait(iteration_number, state)
+    }
+}
+
+/// How to wait for a job to finish, from the `--poll-interval`, `--wait-timeout`, and `--quiet` options
+/// shared by `analyze` and `job wait`.
+#[derive(Clone, Copy)]
+pub struct WaitOptions {
+    poll_interval: Option<Duration>,
+    timeout: Option<Duration>,
+    quiet: bool,
+}
+impl WaitOptions {
+    fn args() -> Vec<Arg<'static, 'static>> {
+        vec![
+            Arg::with_name("poll-interval")
+                .long("poll-interval")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .help("Check on the job every SECONDS seconds, instead of starting at 2 seconds and backing off to 30"),
+            Arg::with_name("wait-timeout")
+                .long("wait-timeout")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .help("Give up waiting after SECONDS seconds, and exit with code 9. The job keeps running on the server."),
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .takes_value(false)
+                .help("Don't report the job's status each time it's checked on"),
+        ]
+    }
+
+    fn parse<'a>(args: &'a ArgMatches) -> Result<WaitOptions, &'a str> {
+        let poll_interval = match args.value_of("poll-interval") {
+            Some(secs) => Some(parse_seconds(secs).ok_or("poll interval should be a positive number of seconds")?),
+            None => None,
+        };
+        let timeout = match args.value_of("wait-timeout") {
+            Some(secs) => Some(parse_seconds(secs).ok_or("wait timeout should be a positive number of seconds")?),
+            None => None,
+        };
+        Ok(WaitOptions { poll_interval, timeout, quiet: args.is_present("quiet") })
+    }
+
+    /// When to give up waiting, if the waiting starts now.
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// How long to wait between checks, giving up at the `deadline`.
+    ///
+    /// Without a `poll_interval`, the waits start at 2 seconds and back off to 30.
+    fn waits(&self, deadline: Option<Instant>) -> Box<dyn PollingStrategy<JobStatusResponse>> {
+        let waits = match self.poll_interval {
+            Some(interval) => Backoff::fixed(interval),
+            None => Backoff { initial: Duration::from_secs(2), max: Duration::from_secs(30) },
+        };
+        match deadline {
+            Some(deadline) => Box::new(Deadline { inner: waits, deadline }),
+            None => Box::new(waits),
+        }
+    }
+
+    fn timeout_seconds(&self) -> u64 {
+        self.timeout.unwrap_or_default().as_secs()
+    }
+}
+
 pub struct AnalyzeCommand;
 pub struct AnalyzeCommandArgs<'a> {
     project_context: ProjectContext,
     branch_name: Option<String>,
     include_git_source: bool,
     git_branch_name: Option<String>,
-    files: Vec<&'a Path>,
-    name: Option<&'a str>
+    inputs: Vec<&'a str>,
+    excludes: Vec<&'a str>,
+    name: Option<&'a str>,
+    fail_on: Vec<FailCondition>,
+    progress: ProgressFormat,
+    wait: WaitOptions,
+    no_wait: bool,
 }
 impl <'a> AnalyzeCommand {
     // ANALYZE - helper for argument extraction
@@ -172,14 +505,33 @@ impl <'a> AnalyzeCommand {
         let include_git_source = analyze_args.is_present("include-git-source");
         // optional branch target name (Git) for the analysis
         let git_branch_name = analyze_args.value_of("git-branch-name").map(|name| name.to_string());
-        // get the list of files
-        let files = analyze_args.values_of("file")
+        // get the list of files, directories, and glob patterns, which are gathered when the command runs
+        let inputs = analyze_args.values_of("file")
             .ok_or("must specify at least one file to analyze")?
-            .map(|file| Path::new(file))
             .collect();
+        // optional patterns for files to leave out of archived directories
+        let excludes = analyze_args.values_of("exclude").map(|excludes| excludes.collect()).unwrap_or_default();
         // optional name for the analysis
         let name = analyze_args.value_of("name");
-        Ok(AnalyzeCommandArgs { project_context, branch_name, include_git_source, git_branch_name, files, name })
+        // optional conditions under which the command should exit with an error
+        let fail_on = match analyze_args.values_of("fail-on") {
+            None => Vec::new(),
+            Some(conditions) => conditions.map(FailCondition::parse).collect::<Result<Vec<_>, _>>()?,
+        };
+        // how to report progress
+        let progress = match analyze_args.value_of("progress") {
+            Some("json") => ProgressFormat::Json,
+            _ => ProgressFormat::Text,
+        };
+        // how to wait for the analysis to finish, if at all
+        let wait = WaitOptions::parse(analyze_args)?;
+        let no_wait = analyze_args.is_present("no-wait");
+        if no_wait && name.is_some() && (include_git_source || git_branch_name.is_some()) {
+            return Err("can't set the name of an analysis that includes git source without waiting for it to start");
+        }
+        Ok(AnalyzeCommandArgs {
+            project_context, branch_name, include_git_source, git_branch_name, inputs, excludes, name, fail_on, progress, wait, no_wait
+        })
     }
 }
 impl <'a> CommandInner<'a> for AnalyzeCommand {
@@ -228,12 +580,48 @@ impl <'a> CommandInner<'a> for AnalyzeCommand {
                 .required(false)
                 .help("Name of the analysis")
             )
+            .arg(Arg::with_name("fail-on")
+                .long("fail-on")
+                .value_name("CONDITION")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Exit with an error if the analysis results meet the given condition, e.g. 'severity>=high,status=new' \
+                or 'count>10'. May be given more than once.")
+            )
+            .arg(Arg::with_name("progress")
+                .long("progress")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .help("How to report progress: 'text' prints '#' comment lines, 'json' prints one JSON event \
+                per line, for parsing by other tools [default: text]")
+            )
+            .args(&WaitOptions::args())
+            .arg(Arg::with_name("no-wait")
+                .long("no-wait")
+                .takes_value(false)
+                .conflicts_with_all(&["fail-on", "poll-interval", "wait-timeout", "quiet"])
+                .help("Don't wait for the analysis to finish; just print its analysis and job IDs. \
+                Use the 'job' command to check on it later.")
+            )
+            .arg(Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("PATTERN")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Leave files matching the .gitignore-style PATTERN out of archived directories, e.g. 'node_modules/' \
+                or '*.min.js'. May be given more than once.")
+            )
             .arg(Arg::with_name("file")
                 .index(2)
                 .value_name("FILE(S)")
                 .takes_value(true)
                 .multiple(true)
                 .required(true)
+                .help("Files, directories, or glob patterns to analyze. Directories are zipped before uploading, \
+                leaving out anything matched by their .gitignore files.")
             )
     }
 
@@ -250,61 +638,225 @@ impl <'a> CommandInner<'a> for AnalyzeCommand {
 
     // ANALYZE - execution
     fn run(&self, client: &ApiClient, args: AnalyzeCommandArgs<'a>) -> CommandResult {
-        let AnalyzeCommandArgs { project_context, branch_name, include_git_source, git_branch_name, files, name } = args;
+        let AnalyzeCommandArgs {
+            project_context, branch_name, include_git_source, git_branch_name, inputs, excludes, name, fail_on, progress, wait, no_wait
+        } = args;
+        let project_context = resolve_context(client, &project_context)?;
+        let inputs = AnalysisInputs::gather(&inputs, &excludes).map_err(|e| {
+            eprintln!("Couldn't gather the files to analyze: {}", e);
+            Exit(EXIT_ERROR)
+        })?;
+        for archive in inputs.archives() {
+            progress.report(AnalyzeEvent::InputArchived { directory: &archive.directory, file_count: archive.file_count });
+        }
+        let files = inputs.files();
+        let during_analysis = |e: ApiError| api_failure("during analysis", &e);
+
+        // the --wait-timeout covers all of the waiting, including for any git source to be prepared
+        let deadline = wait.deadline();
+        let waits = || wait.waits(deadline);
+        let timed_out = |analysis_id, job: &JobStatusResponse| {
+            progress.report(AnalyzeEvent::TimedOut { analysis_id, job, timeout_seconds: wait.timeout_seconds() });
+            Exit(EXIT_TIMED_OUT)
+        };
+
+        // the analysis results end up in the named branch if there is one, so that's where the fail-on conditions look
+        let results_context = match branch_name {
+            Some(ref branch_name) => ProjectContext::for_branch_name(project_context.project_id, branch_name),
+            None => project_context.clone(),
+        };
 
-        let mut analysis_response: ApiResult<ApiAnalysisJobResponse> =
+        let analysis_job_response: ApiAnalysisJobResponse =
             if include_git_source || git_branch_name.is_some() {
-                client.start_analysis_with_git(project_context.clone(), branch_name, include_git_source, git_branch_name, files)
-                    .and_then(|resp| {
-                        println!("# Requesting new analysis with job id {} with included git source", resp.job_id);
-                        client.poll_job_completion(&resp.job_id, Duration::from_secs(2))?;
-                        client.get_job_result(&resp.job_id).map(|result| {
-                            println!("# Started analysis {} with job id {} with included git source", result.analysis_id, resp.job_id);
-                            result
-                        })
-                    })
+                let resp = client.start_analysis_with_git(project_context.clone(), branch_name, include_git_source, git_branch_name, files)
+                    .map_err(during_analysis)?;
+                progress.report(AnalyzeEvent::AnalysisRequested { job_id: &resp.job_id });
+                if no_wait {
+                    // the analysis doesn't exist until this job finishes; 'job result' will tell its ID
+                    return Ok(());
+                }
+                let job = client.poll_job_completion(&resp.job_id, progress.polling(waits(), wait.quiet))
+                    .map_err(during_analysis)?;
+                if !job.status.is_ready() {
+                    return Err(timed_out(None, &job));
+                }
+                let result = client.get_job_result(&resp.job_id).map_err(during_analysis)?;
+                progress.report(AnalyzeEvent::AnalysisStarted { analysis_id: result.analysis_id, job_id: &result.job_id, git_source: true });
+                result
             } else {
-                client
-                    .start_analysis(project_context.clone(), branch_name, files)
-                    .map(|resp| {
-                        println!("# Started analysis {} with job id {}", resp.analysis_id, resp.job_id);
-                        resp
-                    })
+                let resp = client.start_analysis(project_context.clone(), branch_name, files).map_err(during_analysis)?;
+                progress.report(AnalyzeEvent::AnalysisStarted { analysis_id: resp.analysis_id, job_id: &resp.job_id, git_source: false });
+                resp
             };
+        let analysis_id = analysis_job_response.analysis_id;
+        let job_id = &analysis_job_response.job_id;
 
         // if a name was specified, tell the server to set the name
         if let Some(name) = name {
-            analysis_response = analysis_response.and_then(|analysis_job_response| {
-                let analysis_id = analysis_job_response.analysis_id;
-
-                client.set_analysis_name(project_context.clone(), analysis_id, name)
-                    .map(|_| {
-                        println!("# Set analysis {}'s name to \"{}\"", analysis_id, name);
-                        analysis_job_response
-                    })
-            });
+            client.set_analysis_name(project_context.clone(), analysis_id, name).map_err(during_analysis)?;
+            progress.report(AnalyzeEvent::AnalysisNamed { analysis_id, name });
+        }
+        if no_wait {
+            return Ok(());
         }
 
-        let analysis_result_status = analysis_response
-            .and_then(|analysis_job_response| {
-                let job_id = analysis_job_response.job_id;
-                client.poll_job_completion(&job_id, Duration::from_secs(2))
-            });
+        let job = client.poll_job_completion(job_id, progress.polling(waits(), wait.quiet))
+            .map_err(during_analysis)?;
+        if !job.status.is_ready() {
+            return Err(timed_out(Some(analysis_id), &job));
+        }
+        progress.report(AnalyzeEvent::Finished { analysis_id, job: &job });
 
-        match analysis_result_status {
-            Err(e) => {
-                eprintln!("Error during analysis: {:?}", e);
-                Err(Exit(1))
+        if !job.status.is_success() {
+            eprintln!("Analysis did not complete successfully: {}", job);
+            Err(Exit(EXIT_ANALYSIS_FAILED))
+        } else if fail_on.is_empty() {
+            Ok(())
+        } else {
+            check_fail_conditions(client, &results_context, &fail_on, progress)
+        }
+    }
+}
+
+/// Parse a positive, whole number of seconds.
+fn parse_seconds(input: &str) -> Option<Duration> {
+    input.parse::<u64>().ok().filter(|secs| *secs > 0).map(Duration::from_secs)
+}
+
+/// Count the findings in the given `project_context` that match each of the `conditions`, and request
+/// a non-zero exit if any of the conditions are met.
+fn check_fail_conditions(client: &ApiClient, project_context: &ProjectContext, conditions: &[FailCondition], progress: ProgressFormat) -> CommandResult {
+    let count = |filter: &ApiFindingFilter| client.count_findings(project_context, filter)
+        .map(|count| count as usize)
+        .map_err(|e| api_failure("counting findings for --fail-on", &e));
+    let mut violated = false;
+    for condition in conditions {
+        let num_matching = match condition.count_query() {
+            None => 0,
+            Some(query) => {
+                let mut num_matching = count(&query.filter)?;
+                for excluded in &query.excluded {
+                    num_matching = num_matching.saturating_sub(count(excluded)?);
+                }
+                num_matching
             },
-            Ok(status) => {
-                println!("# Polling done");
-                println!("{:?}", status);
-                Ok(())
+        };
+        if let Some(matching_findings) = condition.evaluate(num_matching) {
+            progress.report(AnalyzeEvent::FailConditionMet { condition: condition.to_string(), matching_findings });
+            violated = true;
+        }
+    }
+    if violated { Err(Exit(EXIT_POLICY_VIOLATION)) } else { Ok(()) }
+}
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: analyses
+// -------------------------------------------------------------------------------------------------
+pub struct AnalysesCommand;
+pub enum AnalysesCommandArgs {
+    List { project_context: ProjectContext },
+    Show { project_id: Option<u32>, analysis_id: u32 },
+}
+impl <'a> AnalysesCommand {
+    fn inner_parse(&self, analyses_args: &'a ArgMatches) -> Result<AnalysesCommandArgs, &'a str> {
+        match analyses_args.subcommand() {
+            ("list", Some(args)) => {
+                let context_arg = args.value_of("project-context").ok_or("project context missing")?;
+                Ok(AnalysesCommandArgs::List { project_context: ProjectContext::parse(context_arg)? })
             },
+            ("show", Some(args)) => Ok(AnalysesCommandArgs::Show {
+                project_id: match args.value_of("project-id") {
+                    None => None,
+                    Some(id) => Some(id.parse::<u32>().map_err(|_| "project id should be a number")?),
+                },
+                analysis_id: args.value_of("analysis-id")
+                    .ok_or("must specify a numerical analysis-id")?
+                    .parse::<u32>()
+                    .map_err(|_| "analysis id should be a number")?,
+            }),
+            _ => Err("must specify one of list or show"),
         }
     }
 }
 
+impl <'a> CommandInner<'a> for AnalysesCommand {
+    type Args = AnalysesCommandArgs;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("analyses")
+            .about("Look through a project's past analyses")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("list")
+                .about("Get a list of a project's analyses")
+                .arg(Arg::with_name("project-context")
+                    .index(1)
+                    .value_name("CONTEXT")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Project context whose analyses to list. Should be in the form of <project-id>, \
+                    <project-id>;branchId=<branch-id>, or <project-id>;branch=<branch-name>. \
+                    With just a project id, the analyses of every branch are listed.")
+                )
+            )
+            .subcommand(SubCommand::with_name("show")
+                .about("Get the details of one analysis")
+                .arg(Arg::with_name("project-id")
+                    .short("p")
+                    .long("project-id")
+                    .value_name("PROJECT_ID")
+                    .help("ID of the project the analysis belongs to. If omitted, each project is searched for the analysis, \
+                    which takes a request per project.")
+                    .takes_value(true)
+                )
+                .arg(Arg::with_name("analysis-id")
+                    .index(1)
+                    .value_name("ANALYSIS_ID")
+                    .help("ID of the analysis")
+                    .takes_value(true)
+                    .required(true)
+                )
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("analyses").map(|analyses_args| self.inner_parse(analyses_args))
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let api_error = |e: ApiError| api_failure("loading analyses", &e);
+        let output_format = client.get_config().output_format;
+
+        match args {
+            AnalysesCommandArgs::List { project_context } => {
+                let project_context = resolve_context(client, &project_context)?;
+                let mut analyses = client.get_analyses(project_context.project_id).map_err(api_error)?;
+                // a resolved context always refers to its branch by id
+                if let Some(BranchSpec::ByBranchId(branch_id)) = project_context.branch_spec {
+                    analyses.retain(|analysis| analysis.branch_id == Some(branch_id));
+                }
+                output_format.print_records(&analyses);
+                Ok(())
+            },
+            AnalysesCommandArgs::Show { project_id: Some(project_id), analysis_id } => {
+                let analysis = client.get_analysis(project_id, analysis_id).map_err(api_error)?;
+                output_format.print_record(&analysis);
+                Ok(())
+            },
+            AnalysesCommandArgs::Show { project_id: None, analysis_id } => {
+                match client.find_analysis(analysis_id).map_err(api_error)? {
+                    Some(analysis) => {
+                        output_format.print_record(&analysis);
+                        Ok(())
+                    },
+                    None => {
+                        eprintln!("Analysis {} does not exist in any project", analysis_id);
+                        Err(Exit(EXIT_NOT_FOUND))
+                    },
+                }
+            },
+        }
+    }
+}
 // -------------------------------------------------------------------------------------------------
 // COMMAND: projects
 // -------------------------------------------------------------------------------------------------
@@ -315,7 +867,7 @@ pub struct ProjectsCommandArgs<'a> {
 impl <'a> ProjectsCommand {
     fn inner_parse(&self, project_args: &'a ArgMatches) -> Result<ProjectsCommandArgs<'a>, &'a str> {
         let mut metadatas = HashMap::new();
-        for mut metadata_values in project_args.values_of("metadata") {
+        if let Some(mut metadata_values) = project_args.values_of("metadata") {
             while let Some(k) = metadata_values.next() {
                 let v = metadata_values.next().ok_or("metadata must be given as key value pairs")?;
                 metadatas.insert(k, v);
@@ -358,11 +910,7 @@ impl <'a> CommandInner<'a> for ProjectsCommand {
     }
 
     fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
-        if let Some(project_args) = matches.subcommand_matches("projects") {
-            Some(self.inner_parse(project_args))
-        } else {
-            None
-        }
+        matches.subcommand_matches("projects").map(|project_args| self.inner_parse(project_args))
     }
 
     fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
@@ -373,89 +921,1442 @@ impl <'a> CommandInner<'a> for ProjectsCommand {
             None => client.get_projects(),
         };
         match plist {
-            Err(e) => {
-                eprintln!("Error loading projects: {:?}", e);
-                Err(Exit(1))
-            },
+            Err(e) => Err(api_failure("loading projects", &e)),
             Ok(projects) => {
-                for project in projects {
-                    println!("{}", serde_json::to_string(&project).unwrap());
-                }
+                client.get_config().output_format.print_records(&projects);
                 Ok(())
             }
         }
     }
 }
 
+// -------------------------------------------------------------------------------------------------
+// COMMAND: project
+// -------------------------------------------------------------------------------------------------
+pub struct ProjectCommand;
+pub enum ProjectCommandArgs<'a> {
+    Create { name: &'a str, parent_id: Option<u32> },
+    Rename { project_id: u32, name: &'a str },
+    Move { project_id: u32, parent_id: Option<u32> },
+    Delete { project_id: u32, confirmed: bool },
+}
+impl <'a> ProjectCommand {
+    fn inner_parse(&self, project_args: &'a ArgMatches) -> Result<ProjectCommandArgs<'a>, &'a str> {
+        let project_id_of = |args: &'a ArgMatches| {
+            args.value_of("project-id")
+                .ok_or("must specify a numerical project-id")?
+                .parse::<u32>()
+                .map_err(|_| "project id should be a number")
+        };
+        let parent_id_of = |args: &'a ArgMatches| {
+            match args.value_of("parent") {
+                None => Ok(None),
+                Some(id) => id.parse::<u32>().map(Some).map_err(|_| "parent id should be a number"),
+            }
+        };
+        match project_args.subcommand() {
+            ("create", Some(args)) => Ok(ProjectCommandArgs::Create {
+                name: args.value_of("name").ok_or("must specify a project name")?,
+                parent_id: parent_id_of(args)?,
+            }),
+            ("rename", Some(args)) => Ok(ProjectCommandArgs::Rename {
+                project_id: project_id_of(args)?,
+                name: args.value_of("name").ok_or("must specify a project name")?,
+            }),
+            ("move", Some(args)) => Ok(ProjectCommandArgs::Move {
+                project_id: project_id_of(args)?,
+                parent_id: parent_id_of(args)?,
+            }),
+            ("delete", Some(args)) => Ok(ProjectCommandArgs::Delete {
+                project_id: project_id_of(args)?,
+                confirmed: args.is_present("yes"),
+            }),
+            _ => Err("must specify one of create, rename, move, or delete"),
+        }
+    }
+}
+impl <'a> CommandInner<'a> for ProjectCommand {
+    type Args = ProjectCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        let project_id_arg = || Arg::with_name("project-id")
+            .index(1)
+            .value_name("PROJECT_ID")
+            .takes_value(true)
+            .required(true)
+            .help("ID of the project");
+
+        SubCommand::with_name("project")
+            .about("Create, rename, move, or delete a project")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("create")
+                .about("Create a new project, printing it once created")
+                .arg(Arg::with_name("name")
+                    .index(1)
+                    .value_name("NAME")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Name of the new project")
+                )
+                .arg(Arg::with_name("parent")
+                    .long("parent")
+                    .value_name("PARENT_ID")
+                    .takes_value(true)
+                    .help("ID of the project group to create the project in")
+                )
+            )
+            .subcommand(SubCommand::with_name("rename")
+                .about("Change a project's name")
+                .arg(project_id_arg())
+                .arg(Arg::with_name("name")
+                    .index(2)
+                    .value_name("NAME")
+                    .takes_value(true)
+                    .required(true)
+                    .help("New name for the project")
+                )
+            )
+            .subcommand(SubCommand::with_name("move")
+                .about("Move a project into a project group, or out of one")
+                .arg(project_id_arg())
+                .arg(Arg::with_name("parent")
+                    .long("parent")
+                    .value_name("PARENT_ID")
+                    .takes_value(true)
+                    .required_unless("top-level")
+                    .help("ID of the project group to move the project into")
+                )
+                .arg(Arg::with_name("top-level")
+                    .long("top-level")
+                    .conflicts_with("parent")
+                    .help("Move the project out of its group, to the top level")
+                )
+            )
+            .subcommand(SubCommand::with_name("delete")
+                .about("Delete a project, along with all of its analyses and findings")
+                .arg(project_id_arg())
+                .arg(Arg::with_name("yes")
+                    .short("y")
+                    .long("yes")
+                    .help("Delete without asking for confirmation. Required with --no-prompt.")
+                )
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("project").map(|project_args| self.inner_parse(project_args))
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let result = match args {
+            ProjectCommandArgs::Create { name, parent_id } => {
+                client.create_project(name, parent_id).map(|project| {
+                    client.get_config().output_format.print_record(&project);
+                })
+            },
+            ProjectCommandArgs::Rename { project_id, name } => {
+                client.rename_project(project_id, name).map(|_| {
+                    println!("# Renamed project {} to \"{}\"", project_id, name);
+                })
+            },
+            ProjectCommandArgs::Move { project_id, parent_id } => {
+                client.move_project(project_id, parent_id).map(|_| {
+                    match parent_id {
+                        Some(parent_id) => println!("# Moved project {} into project {}", project_id, parent_id),
+                        None => println!("# Moved project {} to the top level", project_id),
+                    }
+                })
+            },
+            ProjectCommandArgs::Delete { project_id, confirmed } => {
+                if !confirmed {
+                    // with --no-prompt, stdin may be the commands being run, so don't read an answer from it
+                    if client.get_config().no_prompt {
+                        eprintln!("Not deleting project {} without confirmation; use --yes to confirm", project_id);
+                        return Err(Exit(EXIT_ERROR));
+                    }
+                    if !confirm(&format!("Delete project {} and all of its analyses and findings?", project_id)) {
+                        eprintln!("Cancelled; project {} was not deleted", project_id);
+                        return Err(Exit(EXIT_ERROR));
+                    }
+                }
+                client.delete_project(project_id).map(|_| {
+                    println!("# Deleted project {}", project_id);
+                })
+            },
+        };
+        result.map_err(|e| api_failure("updating project", &e))
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: metadata
+// -------------------------------------------------------------------------------------------------
+pub struct MetadataCommand;
+pub enum MetadataCommandArgs<'a> {
+    Get { project_id: u32, fields: Vec<&'a str> },
+    Set { project_id: u32, values: HashMap<&'a str, &'a str> },
+}
+impl <'a> MetadataCommand {
+    fn inner_parse(&self, metadata_args: &'a ArgMatches) -> Result<MetadataCommandArgs<'a>, &'a str> {
+        let project_id_of = |args: &'a ArgMatches| {
+            args.value_of("project-id")
+                .ok_or("must specify a numerical project-id")?
+                .parse::<u32>()
+                .map_err(|_| "project id should be a number")
+        };
+        match metadata_args.subcommand() {
+            ("get", Some(args)) => Ok(MetadataCommandArgs::Get {
+                project_id: project_id_of(args)?,
+                fields: args.values_of("field").map(|fields| fields.collect()).unwrap_or_default(),
+            }),
+            ("set", Some(args)) => {
+                let mut values = HashMap::new();
+                if let Some(mut metadata_values) = args.values_of("metadata") {
+                    while let Some(k) = metadata_values.next() {
+                        let v = metadata_values.next().ok_or("metadata must be given as key value pairs")?;
+                        values.insert(k, v);
+                    }
+                }
+                Ok(MetadataCommandArgs::Set { project_id: project_id_of(args)?, values })
+            },
+            _ => Err("must specify one of get or set"),
+        }
+    }
+}
+impl <'a> CommandInner<'a> for MetadataCommand {
+    type Args = MetadataCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        let project_id_arg = || Arg::with_name("project-id")
+            .index(1)
+            .value_name("PROJECT_ID")
+            .takes_value(true)
+            .required(true)
+            .help("ID of the project");
+
+        SubCommand::with_name("metadata")
+            .about("Read or update a project's metadata fields")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("get")
+                .about("Print a project's metadata as a JSON object of field names to values")
+                .arg(project_id_arg())
+                .arg(Arg::with_name("field")
+                    .index(2)
+                    .value_name("FIELD")
+                    .takes_value(true)
+                    .multiple(true)
+                    .help("Names of the fields to print. If omitted, all fields are printed.")
+                )
+            )
+            .subcommand(SubCommand::with_name("set")
+                .about("Set one or more of a project's metadata fields")
+                .arg(project_id_arg())
+                .arg(Arg::with_name("metadata")
+                    .short("m")
+                    .long("metadata")
+                    .number_of_values(2)
+                    .value_names(&["FIELD", "VALUE"])
+                    .help("Field name and the value to give it")
+                    .multiple(true)
+                    .required(true)
+                )
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("metadata").map(|metadata_args| self.inner_parse(metadata_args))
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let result = match args {
+            MetadataCommandArgs::Get { project_id, fields } => {
+                client.get_project_metadata(project_id).and_then(|mut metadata| {
+                    if !fields.is_empty() {
+                        // like 'metadata set', asking for a field that doesn't exist is an error rather than nothing
+                        if let Some(unknown) = fields.iter().find(|field| !metadata.keys().any(|name| name.eq_ignore_ascii_case(field))) {
+                            return Err(ApiError::UnknownMetadataField(unknown.to_string()));
+                        }
+                        metadata.retain(|name, _| fields.iter().any(|field| field.eq_ignore_ascii_case(name)));
+                    }
+                    client.get_config().output_format.print_record(&metadata);
+                    Ok(())
+                })
+            },
+            MetadataCommandArgs::Set { project_id, values } => {
+                client.set_project_metadata(project_id, &values).map(|_| {
+                    println!("# Updated {} metadata field(s) for project {}", values.len(), project_id);
+                })
+            },
+        };
+        result.map_err(|e| api_failure("accessing project metadata", &e))
+    }
+}
+
 // -------------------------------------------------------------------------------------------------
 // COMMAND: branches
 // -------------------------------------------------------------------------------------------------
 pub struct BranchesCommand;
-pub struct BranchesCommandArgs<'a> {
-    filter: ApiBranchFilter<'a>
+pub enum BranchesCommandArgs<'a> {
+    List { filter: ApiBranchFilter<'a> },
+    Create { project_id: u32, name: &'a str, parent_branch_id: Option<u32> },
+    Delete { project_id: u32, branch_id: u32 },
+    SetDefault { project_id: u32, branch_id: u32 },
 }
 impl <'a> BranchesCommand {
     fn inner_parse(&self, branch_args: &'a ArgMatches) -> Result<BranchesCommandArgs<'a>, &'a str> {
-        let project_id = branch_args
-            .value_of("project-id")
-            .ok_or("must specify a numerical project-id")?
-            .parse::<u32>()
-            .map_err(|_| "project id should be a number")?;
-        let name = branch_args.value_of("name");
-        Ok(BranchesCommandArgs { filter: ApiBranchFilter { project_id, name } })
+        let project_id_of = |args: &'a ArgMatches| {
+            args.value_of("project-id")
+                .ok_or("must specify a numerical project-id")?
+                .parse::<u32>()
+                .map_err(|_| "project id should be a number")
+        };
+        let branch_id_of = |args: &'a ArgMatches| {
+            args.value_of("branch-id")
+                .ok_or("must specify a numerical branch-id")?
+                .parse::<u32>()
+                .map_err(|_| "branch id should be a number")
+        };
+        let filter_of = |args: &'a ArgMatches| -> Result<ApiBranchFilter<'a>, &'a str> {
+            Ok(ApiBranchFilter {
+                project_id: project_id_of(args)?,
+                name: args.value_of("name"),
+                name_match: BranchNameMatch::parse(args.value_of("match").unwrap_or("contains"))?,
+            })
+        };
+        match branch_args.subcommand() {
+            // "branches" with no subcommand is the same as "branches list"
+            ("list", Some(args)) => Ok(BranchesCommandArgs::List { filter: filter_of(args)? }),
+            ("", None) => Ok(BranchesCommandArgs::List { filter: filter_of(branch_args)? }),
+            ("create", Some(args)) => {
+                let parent_branch_id = match args.value_of("parent") {
+                    None => None,
+                    Some(id) => Some(id.parse::<u32>().map_err(|_| "parent branch id should be a number")?),
+                };
+                Ok(BranchesCommandArgs::Create {
+                    project_id: project_id_of(args)?,
+                    name: args.value_of("name").ok_or("must specify a branch name")?,
+                    parent_branch_id,
+                })
+            },
+            ("delete", Some(args)) => Ok(BranchesCommandArgs::Delete {
+                project_id: project_id_of(args)?,
+                branch_id: branch_id_of(args)?,
+            }),
+            ("set-default", Some(args)) => Ok(BranchesCommandArgs::SetDefault {
+                project_id: project_id_of(args)?,
+                branch_id: branch_id_of(args)?,
+            }),
+            _ => Err("must specify one of list, create, delete, or set-default"),
+        }
     }
 }
+
 impl <'a> CommandInner<'a> for BranchesCommand {
     type Args = BranchesCommandArgs<'a>;
 
     fn as_subcommand(&self) -> App<'static, 'static> {
+        let project_id_arg = |required: bool| Arg::with_name("project-id")
+            .short("p")
+            .long("project-id")
+            .value_name("PROJECT_ID")
+            .help("Provide project scope for branch ID lookup")
+            .takes_value(true)
+            .required(required);
+        let name_filter_arg = || Arg::with_name("name")
+            .short("n")
+            .long("name")
+            .value_name("PART_OF_NAME")
+            .help("Provide criteria by branch name matching (see --match)")
+            .takes_value(true)
+            .required(false);
+        let name_match_arg = || Arg::with_name("match")
+            .long("match")
+            .value_name("MODE")
+            .help("How --name is compared to branch names [default: contains]")
+            .takes_value(true)
+            .possible_values(&["contains", "exact", "prefix", "regex"])
+            .requires("name");
+        let branch_id_arg = || Arg::with_name("branch-id")
+            .index(1)
+            .value_name("BRANCH_ID")
+            .help("ID of the branch")
+            .takes_value(true)
+            .required(true);
+
         SubCommand::with_name("branches")
-            .about("Get a list of branches for a project")
-            .arg(Arg::with_name("project-id")
-                .short("p")
-                .long("project-id")
-                .value_name("PROJECT_ID")
-                .help("Provide project scope for branch ID lookup")
-                .takes_value(true)
-                .required(true)
+            .about("List, create, and delete a project's branches")
+            .long_about("List, create, and delete a project's branches. \
+            Without a subcommand, this lists branches the same way 'branches list' does.")
+            // these args are for listing branches when there is no subcommand
+            .arg(project_id_arg(false))
+            .arg(name_filter_arg())
+            .arg(name_match_arg())
+            .subcommand(SubCommand::with_name("list")
+                .about("Get a list of branches for a project")
+                .arg(project_id_arg(true))
+                .arg(name_filter_arg())
+                .arg(name_match_arg())
             )
-            .arg(Arg::with_name("name")
-                .short("n")
-                .long("name")
-                .value_name("PART_OF_NAME")
-                .help("Provide criteria by case-insensitive name matching")
-                .takes_value(true)
-                .required(false)
+            .subcommand(SubCommand::with_name("create")
+                .about("Create a new branch, printing it once created")
+                .arg(project_id_arg(true))
+                .arg(Arg::with_name("name")
+                    .index(1)
+                    .value_name("NAME")
+                    .help("Name of the new branch")
+                    .takes_value(true)
+                    .required(true)
+                )
+                .arg(Arg::with_name("parent")
+                    .long("parent")
+                    .value_name("PARENT_BRANCH_ID")
+                    .help("ID of the branch to create the new branch from. Defaults to the project's default branch.")
+                    .takes_value(true)
+                )
+            )
+            .subcommand(SubCommand::with_name("delete")
+                .about("Delete a branch, printing it once deleted")
+                .arg(project_id_arg(true))
+                .arg(branch_id_arg())
+            )
+            .subcommand(SubCommand::with_name("set-default")
+                .about("Make a branch the project's default branch")
+                .arg(project_id_arg(true))
+                .arg(branch_id_arg())
             )
     }
 
     fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
-        if let Some(branches_args) = matches.subcommand_matches("branches") {
-            Some(self.inner_parse(branches_args))
-        } else {
-            None
-        }
+        matches.subcommand_matches("branches").map(|branches_args| self.inner_parse(branches_args))
     }
 
     fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
-        let BranchesCommandArgs { filter } = args;
-
-        let blist = match filter.name {
-            None => client.get_branches_for_project(filter.project_id),
-            Some(branch_name) => client.query_branches_for_project(filter.project_id, branch_name)
-        };
+        let api_error = |e: ApiError| api_failure("accessing branches", &e);
+        let output_format = client.get_config().output_format;
+        let print_branch = |branch: &ApiBranch| output_format.print_record(branch);
 
-        match blist {
-            Err(e) => {
-                eprintln!("Error loading branches: {:?}", e);
-                Err(Exit(1))
+        match args {
+            BranchesCommandArgs::List { filter } => {
+                let blist = match filter.name {
+                    None => client.get_branches_for_project(filter.project_id),
+                    Some(_) => client.query_branches_for_project(&filter)
+                };
+                let branches = blist.map_err(api_error)?;
+                output_format.print_records(&branches);
+                Ok(())
+            },
+            BranchesCommandArgs::Create { project_id, name, parent_branch_id } => {
+                let parent_branch_id = match parent_branch_id {
+                    Some(id) => id,
+                    None => {
+                        let branches = client.get_branches_for_project(project_id).map_err(api_error)?;
+                        match branches.into_iter().find(|branch| branch.is_default) {
+                            Some(default_branch) => default_branch.id,
+                            None => {
+                                eprintln!("Project {} has no default branch; use --parent to choose one", project_id);
+                                return Err(Exit(EXIT_ERROR));
+                            },
+                        }
+                    },
+                };
+                let branch = client.create_branch(project_id, name, parent_branch_id).map_err(api_error)?;
+                print_branch(&branch);
+                Ok(())
+            },
+            BranchesCommandArgs::Delete { project_id, branch_id } => {
+                match client.get_branch(project_id, branch_id).map_err(api_error)? {
+                    Some(branch) => {
+                        client.delete_branch(project_id, branch_id).map_err(api_error)?;
+                        print_branch(&branch);
+                        Ok(())
+                    },
+                    None => {
+                        eprintln!("Branch {} does not exist in project {}", branch_id, project_id);
+                        Err(Exit(EXIT_NOT_FOUND))
+                    },
+                }
             },
-            Ok(branches) => {
-                for branch in branches {
-                    println!("{}", serde_json::to_string(&branch).unwrap());
+            BranchesCommandArgs::SetDefault { project_id, branch_id } => {
+                client.set_default_branch(project_id, branch_id).map_err(api_error)?;
+                if let Some(branch) = client.get_branch(project_id, branch_id).map_err(api_error)? {
+                    print_branch(&branch);
                 }
                 Ok(())
-            }
+            },
         }
     }
 }
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: findings
+// -------------------------------------------------------------------------------------------------
+/// Arguments for narrowing down a set of findings, shared by every command that works with an `ApiFindingFilter`.
+fn finding_filter_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("severity")
+            .short("s")
+            .long("severity")
+            .value_name("SEVERITY")
+            .help("Only include findings with the given severity, e.g. 'Critical' or 'High'")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("status")
+            .long("status")
+            .value_name("STATUS")
+            .help("Only include findings with the given status, e.g. 'New' or 'Escalated'")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("detection-method")
+            .long("detection-method")
+            .value_name("METHOD")
+            .help("Only include findings with the given detection method, e.g. 'Static' or 'Dynamic'")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("tool")
+            .long("tool")
+            .value_name("TOOL")
+            .help("Only include findings reported by the given tool")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("cwe")
+            .long("cwe")
+            .value_name("CWE_ID")
+            .help("Only include findings mapped to the given CWE number")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("path")
+            .long("path")
+            .value_name("GLOB")
+            .help("Only include findings whose location matches the given glob, e.g. 'src/**/*.java'")
+            .takes_value(true),
+        Arg::with_name("rule")
+            .long("rule")
+            .value_name("RULE")
+            .help("Only include findings for the given rule")
+            .takes_value(true),
+    ]
+}
+
+/// Extract an `ApiFindingFilter` from matches for a subcommand that includes the `finding_filter_args`.
+fn parse_finding_filter<'a>(filter_args: &'a ArgMatches) -> Result<ApiFindingFilter<'a>, &'a str> {
+    let list_of = |name: &str| filter_args.values_of(name).map(|values| values.collect::<Vec<_>>());
+    let cwe = match filter_args.values_of("cwe") {
+        None => None,
+        Some(values) => Some(values
+            .map(|v| v.parse::<u32>().map_err(|_| "cwe should be a number"))
+            .collect::<Result<Vec<_>, _>>()?
+        ),
+    };
+    Ok(ApiFindingFilter {
+        severity: list_of("severity"),
+        status: list_of("status"),
+        detection_method: list_of("detection-method"),
+        tool: list_of("tool"),
+        cwe,
+        path: filter_args.value_of("path"),
+        rule: filter_args.value_of("rule"),
+        ids: None,
+        analysis: None,
+    })
+}
+
+pub struct FindingsCommand;
+pub enum FindingsCommandArgs<'a> {
+    Query {
+        project_context: ProjectContext,
+        filter: ApiFindingFilter<'a>,
+        count_only: bool,
+        pagination: Option<ApiPagination>,
+    },
+    /// `findings export`, which only has the `sarif` format for now
+    Export {
+        project_context: ProjectContext,
+        filter: ApiFindingFilter<'a>,
+        output: Option<&'a Path>,
+    },
+}
+impl <'a> FindingsCommand {
+    fn inner_parse(&self, findings_args: &'a ArgMatches) -> Result<FindingsCommandArgs<'a>, &'a str> {
+        if let Some(export_args) = findings_args.subcommand_matches("export") {
+            let context_arg = export_args.value_of("project-context").ok_or("project context missing")?;
+            return Ok(FindingsCommandArgs::Export {
+                project_context: ProjectContext::parse(context_arg)?,
+                filter: parse_finding_filter(export_args)?,
+                output: export_args.value_of("output").map(Path::new),
+            });
+        }
+        let project_context = {
+            let context_arg = findings_args
+                .value_of("project-context")
+                .ok_or("project context missing")?;
+            ProjectContext::parse(context_arg)?
+        };
+        let filter = parse_finding_filter(findings_args)?;
+        let count_only = findings_args.is_present("count");
+        let pagination = match findings_args.value_of("page") {
+            None => None,
+            Some(page) => {
+                let page = page.parse::<u32>().map_err(|_| "page should be a number")?;
+                let per_page = findings_args.value_of("per-page")
+                    .unwrap_or("100")
+                    .parse::<u32>()
+                    .map_err(|_| "per-page should be a number")?;
+                Some(ApiPagination { page, per_page })
+            }
+        };
+        Ok(FindingsCommandArgs::Query { project_context, filter, count_only, pagination })
+    }
+}
+impl <'a> CommandInner<'a> for FindingsCommand {
+    type Args = FindingsCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("findings")
+            .about("Get a list (or count) of findings in a project, or export them")
+            .setting(AppSettings::SubcommandsNegateReqs)
+            .setting(AppSettings::ArgsNegateSubcommands)
+            .arg(Arg::with_name("project-context")
+                .index(1)
+                .value_name("CONTEXT")
+                .takes_value(true)
+                .required(true)
+                .help("Project context to search. Should be in the form of <project-id>, \
+                <project-id>;branchId=<branch-id>, or <project-id>;branch=<branch-name>")
+            )
+            .args(&finding_filter_args())
+            .arg(Arg::with_name("count")
+                .short("c")
+                .long("count")
+                .takes_value(false)
+                .conflicts_with("page")
+                .help("Print the number of matching findings instead of the findings themselves")
+            )
+            .arg(Arg::with_name("page")
+                .long("page")
+                .value_name("PAGE")
+                .takes_value(true)
+                .help("Only print the given (1-based) page of results, instead of all of them")
+            )
+            .arg(Arg::with_name("per-page")
+                .long("per-page")
+                .value_name("COUNT")
+                .takes_value(true)
+                .requires("page")
+                .help("Number of findings per page when using --page [default: 100]")
+            )
+            .subcommand(SubCommand::with_name("export")
+                .about("Export findings in a format other tools understand, e.g. SARIF for code review tools and IDEs")
+                .arg(Arg::with_name("project-context")
+                    .index(1)
+                    .value_name("CONTEXT")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Project context to export. Should be in the form of <project-id>, \
+                    <project-id>;branchId=<branch-id>, or <project-id>;branch=<branch-name>")
+                )
+                .arg(Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .value_name("FORMAT")
+                    .takes_value(true)
+                    .required(true)
+                    .possible_values(&["sarif"])
+                    .help("Format to export the findings in")
+                )
+                .arg(Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .value_name("FILE")
+                    .takes_value(true)
+                    .help("Save the export to FILE instead of printing it")
+                )
+                .args(&finding_filter_args())
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("findings").map(|findings_args| self.inner_parse(findings_args))
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let (project_context, filter, count_only, pagination) = match args {
+            FindingsCommandArgs::Query { project_context, filter, count_only, pagination } => (project_context, filter, count_only, pagination),
+            FindingsCommandArgs::Export { project_context, filter, output } => return export_sarif(client, &project_context, &filter, output),
+        };
+        let project_context = resolve_context(client, &project_context)?;
+
+        if count_only {
+            match client.count_findings(&project_context, &filter) {
+                Err(e) => Err(api_failure("counting findings", &e)),
+                Ok(count) => {
+                    println!("{}", count);
+                    Ok(())
+                }
+            }
+        } else {
+            let flist = match pagination {
+                Some(pagination) => client.query_findings(&project_context, &filter, pagination),
+                None => client.query_all_findings(&project_context, &filter),
+            };
+            match flist {
+                Err(e) => Err(api_failure("loading findings", &e)),
+                Ok(findings) => {
+                    client.get_config().output_format.print_records(&findings);
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+/// Load the details of each finding matching the `filter`, and write them as a SARIF log to `output` (or STDOUT).
+fn export_sarif(client: &ApiClient, project_context: &ProjectContext, filter: &ApiFindingFilter, output: Option<&Path>) -> CommandResult {
+    let project_context = resolve_context(client, project_context)?;
+    let api_error = |e: ApiError| api_failure("exporting findings", &e);
+
+    // the findings query leaves out details like the CWE and the tool results, so get each finding in full
+    let findings = client.query_all_findings(&project_context, filter).map_err(api_error)?;
+    eprintln!("# Loading details for {} finding(s)", findings.len());
+    let mut detailed = Vec::with_capacity(findings.len());
+    for finding in &findings {
+        detailed.push(client.get_finding(finding.id).map_err(api_error)?);
+        // there's one request per finding, so big exports take a while
+        if detailed.len() % 100 == 0 && detailed.len() < findings.len() {
+            eprintln!("# Loaded details for {} of {} finding(s)", detailed.len(), findings.len());
+        }
+    }
+
+    let log = serde_json::to_string_pretty(&sarif::to_sarif(&detailed)).unwrap();
+    match output {
+        None => println!("{}", log),
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, log + "\n") {
+                eprintln!("Couldn't write {}: {}", path.display(), e);
+                return Err(Exit(EXIT_ERROR));
+            }
+            eprintln!("# Wrote {} finding(s) to {}", detailed.len(), path.display());
+        },
+    }
+    Ok(())
+}
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: triage
+// -------------------------------------------------------------------------------------------------
+pub struct TriageCommand;
+pub struct TriageCommandArgs<'a> {
+    project_context: ProjectContext,
+    filter: ApiFindingFilter<'a>,
+    ids_file: Option<&'a Path>,
+    update: ApiStatusUpdate<'a>,
+    dry_run: bool,
+}
+impl <'a> TriageCommand {
+    fn inner_parse(&self, triage_args: &'a ArgMatches) -> Result<TriageCommandArgs<'a>, &'a str> {
+        let project_context = {
+            let context_arg = triage_args
+                .value_of("project-context")
+                .ok_or("project context missing")?;
+            ProjectContext::parse(context_arg)?
+        };
+        let mut filter = parse_finding_filter(triage_args)?;
+        filter.ids = match triage_args.values_of("id") {
+            None => None,
+            Some(values) => Some(values
+                .map(|v| v.parse::<u32>().map_err(|_| "finding id should be a number"))
+                .collect::<Result<Vec<_>, _>>()?
+            ),
+        };
+        let ids_file = triage_args.value_of("ids-file").map(Path::new);
+        if filter.is_empty() && ids_file.is_none() {
+            return Err("must specify finding ids (--id or --ids-file) or at least one filter option");
+        }
+        let update = ApiStatusUpdate {
+            status: triage_args.value_of("set-status").ok_or("must specify a status to set")?,
+            comment: triage_args.value_of("comment").filter(|c| !c.trim().is_empty()).ok_or("must specify a comment")?,
+            assignee: triage_args.value_of("assignee"),
+        };
+        let dry_run = triage_args.is_present("dry-run");
+        Ok(TriageCommandArgs { project_context, filter, ids_file, update, dry_run })
+    }
+}
+
+/// Read finding IDs from a file with one ID per line. Blank lines and `#` comments are ignored.
+fn read_finding_ids(path: &Path) -> Result<Vec<u32>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    contents.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line_number, line)| line.parse::<u32>().map_err(|_| format!("line {} isn't a finding id: {}", line_number, line)))
+        .collect()
+}
+
+impl <'a> CommandInner<'a> for TriageCommand {
+    type Args = TriageCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("triage")
+            .about("Change the status of many findings at once, e.g. to mark them as false positives")
+            .arg(Arg::with_name("project-context")
+                .index(1)
+                .value_name("CONTEXT")
+                .takes_value(true)
+                .required(true)
+                .help("Project context containing the findings. Should be in the form of <project-id>, \
+                <project-id>;branchId=<branch-id>, or <project-id>;branch=<branch-name>")
+            )
+            .arg(Arg::with_name("set-status")
+                .long("set-status")
+                .value_name("STATUS")
+                .takes_value(true)
+                .required(true)
+                .possible_values(&["false-positive", "ignored", "mitigated", "escalated", "assigned"])
+                .help("The status to give the findings")
+            )
+            .arg(Arg::with_name("comment")
+                .short("m")
+                .long("comment")
+                .value_name("TEXT")
+                .takes_value(true)
+                .required(true)
+                .help("Why the status is changing; recorded in each finding's history")
+            )
+            .arg(Arg::with_name("assignee")
+                .long("assignee")
+                .value_name("USERNAME")
+                .takes_value(true)
+                .required_if("set-status", "assigned")
+                .help("Who to assign the findings to, with --set-status assigned")
+            )
+            .arg(Arg::with_name("id")
+                .long("id")
+                .value_name("FINDING_ID")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .help("ID of a finding to change. May be given more than once, or as a comma-separated list.")
+            )
+            .arg(Arg::with_name("ids-file")
+                .long("ids-file")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("File listing the IDs of findings to change, one per line. Blank lines, and anything after a '#' \
+                on a line, are ignored. The IDs are added to any given with --id.")
+            )
+            .args(&finding_filter_args())
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .takes_value(false)
+                .help("Print how many findings would change, without changing them")
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("triage").map(|triage_args| self.inner_parse(triage_args))
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let TriageCommandArgs { project_context, mut filter, ids_file, update, dry_run } = args;
+        let project_context = resolve_context(client, &project_context)?;
+        let api_error = |e: ApiError| api_failure("updating finding status", &e);
+
+        if let Some(ids_file) = ids_file {
+            let mut ids = read_finding_ids(ids_file).map_err(|e| {
+                eprintln!("Couldn't read finding IDs from {}: {}", ids_file.display(), e);
+                Exit(EXIT_ERROR)
+            })?;
+            if ids.is_empty() {
+                eprintln!("{} doesn't list any finding IDs", ids_file.display());
+                return Err(Exit(EXIT_ERROR));
+            }
+            // --id and --ids-file are two ways of writing the same list
+            ids.extend(filter.ids.take().unwrap_or_default());
+            filter.ids = Some(ids);
+        }
+
+        let count = client.count_findings(&project_context, &filter).map_err(api_error)?;
+        if dry_run {
+            println!("# Would set the status of {} finding(s) to {}", count, update.status);
+            return Ok(());
+        }
+        if count == 0 {
+            println!("# No findings matched; nothing to change");
+            return Ok(());
+        }
+
+        let resp = client.update_findings_status(&project_context, &filter, &update).map_err(api_error)?;
+        println!("# Setting the status of {} finding(s) to {} with job id {}", count, update.status, resp.job_id);
+        let polling = ProgressFormat::Text.polling(Backoff::fixed(Duration::from_secs(2)), false);
+        let job = client.poll_job_completion(&resp.job_id, polling).map_err(api_error)?;
+        if !job.status.is_success() {
+            eprintln!("Status update did not complete successfully: {}", job);
+            return Err(Exit(EXIT_ERROR));
+        }
+        println!("# Set the status of {} finding(s) to {}", count, update.status);
+        Ok(())
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: finding
+// -------------------------------------------------------------------------------------------------
+pub struct FindingCommand;
+pub enum FindingCommandArgs<'a> {
+    Show { finding_id: u32 },
+    Comment { finding_id: u32, content: &'a str },
+    History { finding_id: u32 },
+}
+impl <'a> FindingCommand {
+    fn inner_parse(&self, finding_args: &'a ArgMatches) -> Result<FindingCommandArgs<'a>, &'a str> {
+        let finding_id_of = |args: &'a ArgMatches| {
+            args.value_of("finding-id")
+                .ok_or("must specify a numerical finding-id")?
+                .parse::<u32>()
+                .map_err(|_| "finding id should be a number")
+        };
+        match finding_args.subcommand() {
+            ("show", Some(args)) => Ok(FindingCommandArgs::Show { finding_id: finding_id_of(args)? }),
+            ("comment", Some(args)) => Ok(FindingCommandArgs::Comment {
+                finding_id: finding_id_of(args)?,
+                content: args.value_of("text").filter(|text| !text.trim().is_empty()).ok_or("must specify the comment text")?,
+            }),
+            ("history", Some(args)) => Ok(FindingCommandArgs::History { finding_id: finding_id_of(args)? }),
+            _ => Err("must specify one of show, comment, or history"),
+        }
+    }
+}
+
+impl <'a> CommandInner<'a> for FindingCommand {
+    type Args = FindingCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        let finding_id_arg = || Arg::with_name("finding-id")
+            .index(1)
+            .value_name("FINDING_ID")
+            .help("ID of the finding")
+            .takes_value(true)
+            .required(true);
+
+        SubCommand::with_name("finding")
+            .about("Look into a single finding: its details, comments, and history")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("show")
+                .about("Print a finding's details, including its locations, CWE, description, and tool results")
+                .arg(finding_id_arg())
+            )
+            .subcommand(SubCommand::with_name("comment")
+                .about("Add a comment to a finding, printing it once added")
+                .arg(finding_id_arg())
+                .arg(Arg::with_name("text")
+                    .index(2)
+                    .value_name("TEXT")
+                    .help("The comment")
+                    .takes_value(true)
+                    .required(true)
+                )
+            )
+            .subcommand(SubCommand::with_name("history")
+                .about("Print everything that has happened to a finding, oldest first")
+                .arg(finding_id_arg())
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("finding").map(|finding_args| self.inner_parse(finding_args))
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let api_error = |e: ApiError| api_failure("accessing finding", &e);
+        let output_format = client.get_config().output_format;
+
+        match args {
+            FindingCommandArgs::Show { finding_id } => {
+                let finding = client.get_finding(finding_id).map_err(api_error)?;
+                output_format.print_record(&finding);
+                Ok(())
+            },
+            FindingCommandArgs::Comment { finding_id, content } => {
+                let comment = client.add_finding_comment(finding_id, content).map_err(api_error)?;
+                output_format.print_record(&comment);
+                Ok(())
+            },
+            FindingCommandArgs::History { finding_id } => {
+                let history = client.get_finding_history(finding_id).map_err(api_error)?;
+                output_format.print_records(&history);
+                Ok(())
+            },
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: diff
+// -------------------------------------------------------------------------------------------------
+pub struct DiffCommand;
+/// One side of a diff: a project context (e.g. a branch), or an analysis within a project context.
+pub enum DiffSide {
+    Context(ProjectContext),
+    Analysis { project_context: ProjectContext, analysis_id: u32 },
+}
+pub struct DiffCommandArgs<'a> {
+    base: DiffSide,
+    head: DiffSide,
+    filter: ApiFindingFilter<'a>,
+    only: Vec<Change>,
+    fail_on_new: bool,
+}
+impl <'a> DiffCommand {
+    fn inner_parse(&self, diff_args: &'a ArgMatches) -> Result<DiffCommandArgs<'a>, &'a str> {
+        let side_of = |name: &str| -> Result<DiffSide, &'a str> {
+            let input = diff_args.value_of(name).ok_or("must specify what to compare")?;
+            match diff_args.value_of("project") {
+                Some(project_context) if diff_args.is_present("analyses") => Ok(DiffSide::Analysis {
+                    project_context: ProjectContext::parse(project_context)?,
+                    analysis_id: input.parse::<u32>().map_err(|_| "analysis id should be a number")?,
+                }),
+                _ => ProjectContext::parse(input).map(DiffSide::Context),
+            }
+        };
+        let base = side_of("base")?;
+        let head = side_of("head")?;
+        if let (DiffSide::Context(ref base), DiffSide::Context(ref head)) = (&base, &head) {
+            if base.project_id != head.project_id {
+                return Err("can only compare branches of the same project");
+            }
+        }
+        let only = match diff_args.values_of("only") {
+            None => Vec::new(),
+            Some(changes) => changes.map(Change::parse).collect::<Result<Vec<_>, _>>()?,
+        };
+        Ok(DiffCommandArgs {
+            base,
+            head,
+            filter: parse_finding_filter(diff_args)?,
+            only,
+            fail_on_new: diff_args.is_present("fail-on-new"),
+        })
+    }
+}
+
+/// Load the findings on one side of a diff.
+fn diff_side_findings(client: &ApiClient, side: &DiffSide, filter: &ApiFindingFilter) -> Result<Vec<ApiFinding>, Exit> {
+    let api_error = |e: ApiError| api_failure("loading findings to compare", &e);
+    match *side {
+        DiffSide::Context(ref project_context) => {
+            let project_context = resolve_context(client, project_context)?;
+            client.query_all_findings(&project_context, filter).map_err(api_error)
+        },
+        DiffSide::Analysis { ref project_context, analysis_id } => {
+            // findings are looked up per branch, so without a branch given, use the one the analysis ran on
+            let project_context = match project_context.branch_spec {
+                Some(_) => resolve_context(client, project_context)?,
+                None => {
+                    let analysis = client.get_analysis(project_context.project_id, analysis_id).map_err(api_error)?;
+                    match analysis.branch_id {
+                        Some(branch_id) => ProjectContext::for_branch_id(project_context.project_id, branch_id),
+                        None => project_context.clone(),
+                    }
+                },
+            };
+            let filter = ApiFindingFilter { analysis: Some(analysis_id), ..filter.clone() };
+            client.query_all_findings(&project_context, &filter).map_err(api_error)
+        },
+    }
+}
+
+impl <'a> CommandInner<'a> for DiffCommand {
+    type Args = DiffCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("diff")
+            .about("List the findings that are new, fixed, or changed between two branches or analyses")
+            .arg(Arg::with_name("base")
+                .index(1)
+                .value_name("BASE")
+                .takes_value(true)
+                .required(true)
+                .help("What to compare against, e.g. '12;branch=main'. With --analyses, an analysis ID.")
+            )
+            .arg(Arg::with_name("head")
+                .index(2)
+                .value_name("HEAD")
+                .takes_value(true)
+                .required(true)
+                .help("What to compare, e.g. '12;branch=feature-x'. With --analyses, an analysis ID.")
+            )
+            .arg(Arg::with_name("analyses")
+                .long("analyses")
+                .takes_value(false)
+                .requires("project")
+                .help("Compare two analyses, given by ID, instead of two project contexts")
+            )
+            .arg(Arg::with_name("project")
+                .short("p")
+                .long("project")
+                .value_name("CONTEXT")
+                .takes_value(true)
+                .requires("analyses")
+                .help("Project (or project context, e.g. '12;branch=x') containing the analyses, with --analyses. \
+                Without a branch, each analysis is compared within the branch it ran on.")
+            )
+            .arg(Arg::with_name("only")
+                .long("only")
+                .value_name("CHANGE")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .possible_values(&["new", "fixed", "changed"])
+                .help("Only list the given kinds of change, e.g. 'new' or 'new,changed'")
+            )
+            .arg(Arg::with_name("fail-on-new")
+                .long("fail-on-new")
+                .takes_value(false)
+                .help("Exit with code 4 if there are any new findings")
+            )
+            .args(&finding_filter_args())
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("diff").map(|diff_args| self.inner_parse(diff_args))
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let DiffCommandArgs { base, head, filter, only, fail_on_new } = args;
+        let base_findings = diff_side_findings(client, &base, &filter)?;
+        let head_findings = diff_side_findings(client, &head, &filter)?;
+
+        let mut changes = diff::diff_findings(&base_findings, &head_findings);
+        let count_of = |changes: &[diff::FindingChange], kind: Change| changes.iter().filter(|c| c.change == kind).count();
+        let num_new = count_of(&changes, Change::New);
+        eprintln!("# {} new, {} fixed, {} changed", num_new, count_of(&changes, Change::Fixed), count_of(&changes, Change::Changed));
+        if !only.is_empty() {
+            changes.retain(|c| only.contains(&c.change));
+        }
+        client.get_config().output_format.print_records(&changes);
+
+        if fail_on_new && num_new > 0 {
+            Err(Exit(EXIT_POLICY_VIOLATION))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: report
+// -------------------------------------------------------------------------------------------------
+pub struct ReportCommand;
+pub struct ReportCommandArgs<'a> {
+    project_context: ProjectContext,
+    filter: ApiFindingFilter<'a>,
+    config: ApiReportConfig,
+    output: &'a Path,
+}
+impl <'a> ReportCommand {
+    fn inner_parse(&self, report_args: &'a ArgMatches) -> Result<ReportCommandArgs<'a>, &'a str> {
+        let project_context = {
+            let context_arg = report_args
+                .value_of("project-context")
+                .ok_or("project context missing")?;
+            ProjectContext::parse(context_arg)?
+        };
+        let filter = parse_finding_filter(report_args)?;
+        let output = report_args.value_of("output").map(Path::new).ok_or("must specify an output file")?;
+        let string_of = |name: &str, default: &str| report_args.value_of(name).unwrap_or(default).to_string();
+        let config = match report_args.value_of("format") {
+            Some("pdf") => ApiReportConfig::Pdf {
+                summary_mode: string_of("summary-mode", "detailed"),
+                details_mode: string_of("details-mode", "with-source"),
+                include_result_details: report_args.is_present("include-result-details"),
+                include_comments: report_args.is_present("include-comments"),
+                include_request_response: report_args.is_present("include-request-response"),
+            },
+            Some("csv") => ApiReportConfig::Csv {
+                columns: report_args.values_of("columns")
+                    .map(|columns| columns.map(String::from).collect())
+                    .unwrap_or_else(|| DEFAULT_CSV_COLUMNS.iter().map(|c| c.to_string()).collect()),
+            },
+            Some("xml") => ApiReportConfig::Xml {
+                include_standards: report_args.is_present("include-standards"),
+                include_source: report_args.is_present("include-source"),
+                include_rule_descriptions: report_args.is_present("include-rule-descriptions"),
+            },
+            Some("nessus") => ApiReportConfig::Nessus {
+                default_host: string_of("default-host", ""),
+                operating_system: string_of("operating-system", ""),
+                mac_address: string_of("mac-address", ""),
+                netbios_name: string_of("netbios-name", ""),
+            },
+            _ => return Err("format should be one of pdf, csv, xml, or nessus"),
+        };
+        Ok(ReportCommandArgs { project_context, filter, config, output })
+    }
+}
+
+/// Columns included in a CSV report when `--columns` isn't given.
+const DEFAULT_CSV_COLUMNS: &[&str] = &["id", "creationDate", "severity", "status", "cwe", "rule", "tool", "location", "element", "loc.path", "loc.line"];
+
+impl <'a> CommandInner<'a> for ReportCommand {
+    type Args = ReportCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        SubCommand::with_name("report")
+            .about("Generate a project report and download it to a file")
+            .arg(Arg::with_name("project-context")
+                .index(1)
+                .value_name("CONTEXT")
+                .takes_value(true)
+                .required(true)
+                .help("Project context to report on. Should be in the form of <project-id>, \
+                <project-id>;branchId=<branch-id>, or <project-id>;branch=<branch-name>")
+            )
+            .arg(Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .required(true)
+                .possible_values(&["pdf", "csv", "xml", "nessus"])
+                .help("Type of report to generate")
+            )
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+                .help("Path where the finished report will be saved")
+            )
+            .args(&finding_filter_args())
+            // pdf options
+            .arg(Arg::with_name("summary-mode")
+                .long("summary-mode")
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(&["simple", "detailed"])
+                .help("[pdf] How much detail to include in the report summary [default: detailed]")
+            )
+            .arg(Arg::with_name("details-mode")
+                .long("details-mode")
+                .value_name("MODE")
+                .takes_value(true)
+                .possible_values(&["none", "simple", "with-source"])
+                .help("[pdf] How much detail to include for each finding [default: with-source]")
+            )
+            .arg(Arg::with_name("include-result-details")
+                .long("include-result-details")
+                .help("[pdf] Include the details of each tool result")
+            )
+            .arg(Arg::with_name("include-comments")
+                .long("include-comments")
+                .help("[pdf] Include finding comments")
+            )
+            .arg(Arg::with_name("include-request-response")
+                .long("include-request-response")
+                .help("[pdf] Include HTTP requests and responses for dynamic findings")
+            )
+            // csv options
+            .arg(Arg::with_name("columns")
+                .long("columns")
+                .value_name("COLUMNS")
+                .takes_value(true)
+                .use_delimiter(true)
+                .help("[csv] Comma-separated list of columns to include")
+            )
+            // xml options
+            .arg(Arg::with_name("include-standards")
+                .long("include-standards")
+                .help("[xml] Include standards violations, e.g. OWASP Top 10")
+            )
+            .arg(Arg::with_name("include-source")
+                .long("include-source")
+                .help("[xml] Include source code snippets")
+            )
+            .arg(Arg::with_name("include-rule-descriptions")
+                .long("include-rule-descriptions")
+                .help("[xml] Include rule descriptions")
+            )
+            // nessus options
+            .arg(Arg::with_name("default-host")
+                .long("default-host")
+                .value_name("HOST")
+                .takes_value(true)
+                .help("[nessus] Host name to use for findings that aren't associated with a host")
+            )
+            .arg(Arg::with_name("operating-system")
+                .long("operating-system")
+                .value_name("OS")
+                .takes_value(true)
+                .help("[nessus] Operating system to report for the default host")
+            )
+            .arg(Arg::with_name("mac-address")
+                .long("mac-address")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .help("[nessus] MAC address to report for the default host")
+            )
+            .arg(Arg::with_name("netbios-name")
+                .long("netbios-name")
+                .value_name("NAME")
+                .takes_value(true)
+                .help("[nessus] NetBIOS name to report for the default host")
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("report").map(|report_args| self.inner_parse(report_args))
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let ReportCommandArgs { project_context, filter, config, output } = args;
+        let project_context = resolve_context(client, &project_context)?;
+
+        let report_error = |e: ApiError| api_failure("generating report", &e);
+
+        let resp = client.generate_report(&project_context, &filter, &config).map_err(report_error)?;
+        println!("# Generating {} report with job id {}", config.report_type(), resp.job_id);
+        let polling = ProgressFormat::Text.polling(Backoff::fixed(Duration::from_secs(2)), false);
+        let job = client.poll_job_completion(&resp.job_id, polling).map_err(report_error)?;
+        if !job.status.is_success() {
+            eprintln!("Report generation did not complete successfully: {}", job);
+            return Err(Exit(EXIT_ERROR));
+        }
+        let num_bytes = client.download_job_result(&resp.job_id, output).map_err(report_error)?;
+        println!("# Wrote {} bytes to {}", num_bytes, output.display());
+        Ok(())
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// COMMAND: job
+// -------------------------------------------------------------------------------------------------
+pub struct JobCommand;
+pub enum JobCommandArgs<'a> {
+    Status { job_id: &'a str },
+    Wait { job_id: &'a str, wait: WaitOptions },
+    Result { job_id: &'a str, output: Option<&'a Path> },
+    Cancel { job_id: &'a str },
+}
+impl <'a> JobCommand {
+    fn inner_parse(&self, job_args: &'a ArgMatches) -> Result<JobCommandArgs<'a>, &'a str> {
+        let job_id_of = |args: &'a ArgMatches| args.value_of("job-id").ok_or("must specify a job id");
+        match job_args.subcommand() {
+            ("status", Some(args)) => Ok(JobCommandArgs::Status { job_id: job_id_of(args)? }),
+            ("wait", Some(args)) => Ok(JobCommandArgs::Wait { job_id: job_id_of(args)?, wait: WaitOptions::parse(args)? }),
+            ("result", Some(args)) => Ok(JobCommandArgs::Result {
+                job_id: job_id_of(args)?,
+                output: args.value_of("output").map(Path::new),
+            }),
+            ("cancel", Some(args)) => Ok(JobCommandArgs::Cancel { job_id: job_id_of(args)? }),
+            _ => Err("must specify one of status, wait, result, or cancel"),
+        }
+    }
+}
+
+impl <'a> CommandInner<'a> for JobCommand {
+    type Args = JobCommandArgs<'a>;
+
+    fn as_subcommand(&self) -> App<'static, 'static> {
+        let job_id_arg = || Arg::with_name("job-id")
+            .index(1)
+            .value_name("JOB_ID")
+            .help("ID of the job, as printed by e.g. 'analyze' or 'report'")
+            .takes_value(true)
+            .required(true);
+
+        SubCommand::with_name("job")
+            .about("Check on, wait for, or cancel a job, e.g. one started by 'analyze --no-wait'")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("status")
+                .about("Print a job's current status")
+                .arg(job_id_arg())
+            )
+            .subcommand(SubCommand::with_name("wait")
+                .about("Wait for a job to finish, exiting with code 3 if it doesn't complete successfully")
+                .arg(job_id_arg())
+                .args(&WaitOptions::args())
+            )
+            .subcommand(SubCommand::with_name("result")
+                .about("Print the result of a completed job, or save it to a file with --output")
+                .arg(job_id_arg())
+                .arg(Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .value_name("FILE")
+                    .takes_value(true)
+                    .help("Save the result to FILE instead of printing it, e.g. for a report job")
+                )
+            )
+            .subcommand(SubCommand::with_name("cancel")
+                .about("Cancel a queued or running job")
+                .arg(job_id_arg())
+            )
+    }
+
+    fn parse(&self, matches: &'a ArgMatches) -> Option<Result<Self::Args, &'a str>> {
+        matches.subcommand_matches("job").map(|job_args| self.inner_parse(job_args))
+    }
+
+    fn run(&self, client: &ApiClient, args: Self::Args) -> CommandResult {
+        let api_error = |e: ApiError| api_failure("accessing job", &e);
+        let output_format = client.get_config().output_format;
+
+        match args {
+            JobCommandArgs::Status { job_id } => {
+                let job = client.get_job(job_id).map_err(api_error)?;
+                output_format.print_record(&job);
+                Ok(())
+            },
+            JobCommandArgs::Wait { job_id, wait } => {
+                let polling = ProgressFormat::Text.polling(wait.waits(wait.deadline()), wait.quiet);
+                let job = client.poll_job_completion(job_id, polling).map_err(api_error)?;
+                if !job.status.is_ready() {
+                    let timed_out = AnalyzeEvent::TimedOut { analysis_id: None, job: &job, timeout_seconds: wait.timeout_seconds() };
+                    ProgressFormat::Text.report(timed_out);
+                    Err(Exit(EXIT_TIMED_OUT))
+                } else if !job.status.is_success() {
+                    eprintln!("Job {} did not complete successfully: {}", job_id, job);
+                    Err(Exit(EXIT_ANALYSIS_FAILED))
+                } else {
+                    println!("{}", job);
+                    Ok(())
+                }
+            },
+            JobCommandArgs::Result { job_id, output: Some(output) } => {
+                let num_bytes = client.download_job_result(job_id, output).map_err(api_error)?;
+                println!("# Wrote {} bytes to {}", num_bytes, output.display());
+                Ok(())
+            },
+            JobCommandArgs::Result { job_id, output: None } => {
+                let result = client.get_job_result_json(job_id).map_err(api_error)?;
+                output_format.print_record(&result);
+                Ok(())
+            },
+            JobCommandArgs::Cancel { job_id } => {
+                client.cancel_job(job_id).map_err(api_error)?;
+                println!("# Cancelled job {}", job_id);
+                Ok(())
+            },
+        }
+    }
+}
+
+#[test]
+fn test_read_finding_ids(){
+    let path = std::env::temp_dir().join(format!("codedx-client-test-ids-{}", std::process::id()));
+    std::fs::write(&path, "# reviewed 2024-03-04\n101\n\n  102  # inline comment\r\n#103\n104\n").unwrap();
+    let ids = read_finding_ids(&path);
+    std::fs::write(&path, "101\n10x2\n").unwrap();
+    let invalid = read_finding_ids(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(ids.unwrap() == vec![101, 102, 104]);
+    assert!(invalid.unwrap_err() == "line 2 isn't a finding id: 10x2");
+    assert!(read_finding_ids(Path::new("/does/not/exist")).is_err());
+}
@@ -0,0 +1,200 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use reqwest::Url;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, ClientAuth};
+
+/// Length of the nonce that starts the encrypted credentials file
+const NONCE_LEN: usize = 12;
+
+/// Credentials saved by the `login` command, keyed by base URL.
+///
+/// The credentials are kept in an AES-256-GCM encrypted file, `credentials`, next to the config file.
+/// The key lives in a separate file, `credentials.key`, which only the current user may read.
+/// This keeps the credentials out of plain sight (e.g. from a `grep`, or a backup that skips the key),
+/// but anyone who can read both files as the current user can still decrypt them.
+pub struct CredentialStore {
+    path: PathBuf,
+    key_path: PathBuf,
+}
+
+/// Things that can go wrong when reading or writing the `CredentialStore`
+#[derive(Debug)]
+pub enum CredentialStoreError {
+    IO(io::Error),
+    /// The credentials file couldn't be decrypted or parsed
+    Corrupt,
+}
+impl From<io::Error> for CredentialStoreError {
+    fn from(err: io::Error) -> CredentialStoreError {
+        CredentialStoreError::IO(err)
+    }
+}
+impl fmt::Display for CredentialStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CredentialStoreError::IO(ref e) => write!(f, "{}", e),
+            CredentialStoreError::Corrupt => write!(f, "the stored credentials are unreadable; run 'logout' to clear them"),
+        }
+    }
+}
+
+pub type CredentialStoreResult<T> = Result<T, CredentialStoreError>;
+
+impl CredentialStore {
+    /// The store in the directory returned by `config::config_dir`, if there is one.
+    pub fn default_location() -> Option<CredentialStore> {
+        config::config_dir().map(|dir| CredentialStore::in_dir(&dir))
+    }
+
+    pub fn in_dir(dir: &Path) -> CredentialStore {
+        CredentialStore {
+            path: dir.join("credentials"),
+            key_path: dir.join("credentials.key"),
+        }
+    }
+
+    pub fn get(&self, base_url: &Url) -> CredentialStoreResult<Option<ClientAuth>> {
+        let mut entries = self.load()?;
+        Ok(entries.remove(&store_key(base_url)))
+    }
+
+    pub fn put(&self, base_url: &Url, auth: &ClientAuth) -> CredentialStoreResult<()> {
+        let mut entries = self.load()?;
+        entries.insert(store_key(base_url), auth.clone());
+        self.save(&entries)
+    }
+
+    /// Forget the credentials for `base_url`, returning whether there were any.
+    pub fn remove(&self, base_url: &Url) -> CredentialStoreResult<bool> {
+        let mut entries = match self.load() {
+            Ok(entries) => entries,
+            // let a corrupt store be cleared out, since there's no other way to get rid of it
+            Err(CredentialStoreError::Corrupt) => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        let removed = entries.remove(&store_key(base_url)).is_some();
+        if entries.is_empty() {
+            remove_if_exists(&self.path)?;
+            remove_if_exists(&self.key_path)?;
+        } else {
+            self.save(&entries)?;
+        }
+        Ok(removed)
+    }
+
+    fn load(&self) -> CredentialStoreResult<BTreeMap<String, ClientAuth>> {
+        if !self.path.is_file() {
+            return Ok(BTreeMap::new());
+        }
+        let encrypted = fs::read(&self.path)?;
+        if encrypted.len() < NONCE_LEN || !self.key_path.is_file() {
+            return Err(CredentialStoreError::Corrupt);
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+        let plaintext = self.cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CredentialStoreError::Corrupt)?;
+        serde_json::from_slice(&plaintext).map_err(|_| CredentialStoreError::Corrupt)
+    }
+
+    fn save(&self, entries: &BTreeMap<String, ClientAuth>) -> CredentialStoreResult<()> {
+        let plaintext = serde_json::to_vec(entries).unwrap();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher()?
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| CredentialStoreError::Corrupt)?;
+        let mut contents = nonce.to_vec();
+        contents.extend(ciphertext);
+        write_private(&self.path, &contents)
+    }
+
+    /// Get a cipher using the key from `key_path`, generating that key if it doesn't exist yet.
+    fn cipher(&self) -> CredentialStoreResult<Aes256Gcm> {
+        if !self.key_path.is_file() {
+            let key = Aes256Gcm::generate_key(OsRng);
+            write_private(&self.key_path, &key)?;
+        }
+        let key = fs::read(&self.key_path)?;
+        if key.len() != 32 {
+            return Err(CredentialStoreError::Corrupt);
+        }
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    }
+}
+
+/// Credentials are stored per base URL, ignoring any trailing slash.
+fn store_key(base_url: &Url) -> String {
+    base_url.as_str().trim_end_matches('/').to_string()
+}
+
+/// Write a file that only the current user may read, creating its directory if needed.
+fn write_private(path: &Path, contents: &[u8]) -> CredentialStoreResult<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+#[test]
+fn test_store_round_trip(){
+    let dir = std::env::temp_dir().join(format!("codedx-client-test-store-{}", std::process::id()));
+    let store = CredentialStore::in_dir(&dir);
+    let url = Url::parse("https://localhost/codedx/").unwrap();
+    let other_url = Url::parse("https://example.com/codedx").unwrap();
+
+    store.put(&url, &ClientAuth::ApiKey("api-key:secret".to_string())).unwrap();
+    store.put(&other_url, &ClientAuth::Basic { username: "johndoe".to_string(), password: "hunter2".to_string() }).unwrap();
+    let stored_contents = fs::read(dir.join("credentials")).unwrap();
+    let found = store.get(&Url::parse("https://localhost/codedx").unwrap()).unwrap();
+    let removed = store.remove(&url).unwrap();
+    let removed_again = store.remove(&url).unwrap();
+    let remaining = store.get(&other_url).unwrap();
+    store.remove(&other_url).unwrap();
+    let files_left = dir.join("credentials").exists() || dir.join("credentials.key").exists();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!String::from_utf8_lossy(&stored_contents).contains("secret"));
+    assert!(matches!(found, Some(ClientAuth::ApiKey(ref key)) if key == "api-key:secret"));
+    assert!(removed && !removed_again);
+    assert!(matches!(remaining, Some(ClientAuth::Basic { ref username, .. }) if username == "johndoe"));
+    assert!(!files_left);
+}
@@ -0,0 +1,142 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::client::{ApiFinding, ApiFindingLocation};
+use std::collections::HashMap;
+
+/// How a finding differs between the "base" and "head" sides of a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// Open in the head, but missing or resolved in the base
+    New,
+    /// Open in the base, but missing or resolved in the head
+    Fixed,
+    /// Open on both sides, with a different severity or status
+    Changed,
+}
+impl Change {
+    pub fn parse(input: &str) -> Result<Change, &'static str> {
+        match input {
+            "new" => Ok(Change::New),
+            "fixed" => Ok(Change::Fixed),
+            "changed" => Ok(Change::Changed),
+            _ => Err("change should be one of new, fixed, or changed"),
+        }
+    }
+}
+
+/// A finding that differs between the two sides of a diff, with its details from the side it's open on.
+#[derive(Debug, Serialize)]
+pub struct FindingChange<'a> {
+    pub change: Change,
+    pub id: u32,
+    pub rule: Option<&'a str>,
+    pub severity: Option<&'a str>,
+    pub status: Option<&'a str>,
+    #[serde(rename = "previousSeverity", skip_serializing_if = "Option::is_none")]
+    pub previous_severity: Option<&'a str>,
+    #[serde(rename = "previousStatus", skip_serializing_if = "Option::is_none")]
+    pub previous_status: Option<&'a str>,
+    pub location: Option<&'a ApiFindingLocation>,
+}
+impl <'a> FindingChange<'a> {
+    fn of(change: Change, finding: &'a ApiFinding) -> FindingChange<'a> {
+        FindingChange {
+            change,
+            id: finding.id,
+            rule: finding.descriptor.as_ref().map(|d| d.name.as_str()),
+            severity: severity_of(finding),
+            status: finding.status.as_deref(),
+            previous_severity: None,
+            previous_status: None,
+            location: finding.primary_location.as_ref(),
+        }
+    }
+}
+
+fn severity_of(finding: &ApiFinding) -> Option<&str> {
+    finding.severity.as_ref().map(|s| s.name.as_str())
+}
+
+/// Findings stay around after they're fixed, with a status saying so.
+fn is_open(finding: &ApiFinding) -> bool {
+    !matches!(finding.status.as_deref().map(|s| s.to_lowercase()).as_deref(), Some("fixed") | Some("gone"))
+}
+
+/// Compare the findings from two branches or analyses of the same project, matching them up by ID.
+///
+/// The result lists the new findings first, then the fixed ones, then the changed ones, each in the
+/// order they appear in `head` (or `base`, for the fixed ones).
+pub fn diff_findings<'a>(base: &'a [ApiFinding], head: &'a [ApiFinding]) -> Vec<FindingChange<'a>> {
+    let base_by_id: HashMap<u32, &ApiFinding> = base.iter().map(|f| (f.id, f)).collect();
+    let head_by_id: HashMap<u32, &ApiFinding> = head.iter().map(|f| (f.id, f)).collect();
+    let open_in = |findings: &HashMap<u32, &ApiFinding>, id: u32| findings.get(&id).is_some_and(|f| is_open(f));
+
+    let new = head.iter()
+        .filter(|f| is_open(f) && !open_in(&base_by_id, f.id))
+        .map(|f| FindingChange::of(Change::New, f));
+    let fixed = base.iter()
+        .filter(|f| is_open(f) && !open_in(&head_by_id, f.id))
+        .map(|f| FindingChange::of(Change::Fixed, f));
+    let changed = head.iter()
+        .filter(|f| is_open(f))
+        .filter_map(|f| base_by_id.get(&f.id).filter(|b| is_open(b)).map(|b| (*b, f)))
+        .filter(|(b, f)| severity_of(b) != severity_of(f) || b.status != f.status)
+        .map(|(b, f)| FindingChange {
+            previous_severity: severity_of(b),
+            previous_status: b.status.as_deref(),
+            ..FindingChange::of(Change::Changed, f)
+        });
+
+    new.chain(fixed).chain(changed).collect()
+}
+
+#[test]
+fn test_diff_findings(){
+    let finding = |id: u32, severity: &str, status: &str| -> ApiFinding {
+        serde_json::from_value(json!({
+            "id": id, "descriptor": null, "severity": {"id": null, "name": severity}, "status": status,
+            "detectionMethod": null, "primaryLocation": null,
+        })).unwrap()
+    };
+    let base = vec![
+        finding(1, "High", "new"),
+        finding(2, "High", "new"),
+        finding(3, "Medium", "new"),
+        finding(4, "Low", "fixed"),
+        finding(5, "Low", "new"),
+    ];
+    let head = vec![
+        finding(1, "High", "new"),      // unchanged
+        finding(3, "High", "escalated"), // changed
+        finding(4, "Low", "new"),       // came back
+        finding(5, "Low", "fixed"),     // fixed in the head
+        finding(6, "Critical", "new"),  // new
+    ];
+
+    let changes: Vec<(Change, u32)> = diff_findings(&base, &head).iter().map(|c| (c.change, c.id)).collect();
+    assert!(changes == vec![
+        (Change::New, 4), (Change::New, 6),
+        (Change::Fixed, 2), (Change::Fixed, 5),
+        (Change::Changed, 3),
+    ]);
+
+    let diff = diff_findings(&base, &head);
+    let changed = diff.iter().find(|c| c.change == Change::Changed).unwrap();
+    assert!(changed.severity == Some("High") && changed.previous_severity == Some("Medium"));
+    assert!(changed.status == Some("escalated") && changed.previous_status == Some("new"));
+}
@@ -0,0 +1,217 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// The files to upload for an analysis, gathered from the files, directories, and glob patterns given to `analyze`.
+///
+/// Directories are archived into zip files in a temporary directory, which is removed when this is dropped.
+#[derive(Debug, Default)]
+pub struct AnalysisInputs {
+    files: Vec<PathBuf>,
+    archives: Vec<Archive>,
+    temp_dir: Option<PathBuf>,
+}
+
+/// A directory that was archived into a zip file to be uploaded.
+#[derive(Debug)]
+pub struct Archive {
+    pub directory: PathBuf,
+    pub file_count: usize,
+}
+
+impl AnalysisInputs {
+    /// Gather the inputs, archiving any directories while leaving out the files matched by their
+    /// `.gitignore` files or by the (`.gitignore`-style) `excludes`.
+    pub fn gather(inputs: &[&str], excludes: &[&str]) -> Result<AnalysisInputs, String> {
+        let mut gathered = AnalysisInputs::default();
+        for input in inputs {
+            for path in expand(input)? {
+                if path.is_dir() {
+                    let zip = gathered.archive_path(&path).map_err(|e| format!("couldn't create a temporary directory: {}", e))?;
+                    let file_count = archive_directory(&path, excludes, &zip)
+                        .map_err(|e| format!("couldn't archive {}: {}", path.display(), e))?;
+                    gathered.files.push(zip);
+                    gathered.archives.push(Archive { directory: path, file_count });
+                } else {
+                    gathered.files.push(path);
+                }
+            }
+        }
+        Ok(gathered)
+    }
+
+    /// The files to upload, in the order they were given.
+    pub fn files(&self) -> Vec<&Path> {
+        self.files.iter().map(PathBuf::as_path).collect()
+    }
+
+    pub fn archives(&self) -> &[Archive] {
+        &self.archives
+    }
+
+    /// Where to save the archive of `directory`, named after it so the upload is recognizable on the server.
+    fn archive_path(&mut self, directory: &Path) -> io::Result<PathBuf> {
+        let temp_dir = match self.temp_dir {
+            Some(ref temp_dir) => temp_dir.clone(),
+            None => {
+                // REPL mode can gather inputs more than once, so each time gets its own directory
+                static GATHERED: AtomicUsize = AtomicUsize::new(0);
+                let temp_dir = std::env::temp_dir()
+                    .join(format!("codedx-client-{}-{}", std::process::id(), GATHERED.fetch_add(1, Ordering::Relaxed)));
+                fs::create_dir_all(&temp_dir)?;
+                self.temp_dir = Some(temp_dir.clone());
+                temp_dir
+            },
+        };
+        // e.g. "." has no file name, so name it after where it really is
+        let name = fs::canonicalize(directory)?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "source".to_string());
+        let mut zip = temp_dir.join(format!("{}.zip", name));
+        let mut suffix = 2;
+        while zip.exists() {
+            zip = temp_dir.join(format!("{}-{}.zip", name, suffix));
+            suffix += 1;
+        }
+        Ok(zip)
+    }
+}
+impl Drop for AnalysisInputs {
+    fn drop(&mut self) {
+        if let Some(ref temp_dir) = self.temp_dir {
+            let _ = fs::remove_dir_all(temp_dir);
+        }
+    }
+}
+
+/// Expand a glob pattern into the paths it matches, or pass a plain path through as long as it exists.
+///
+/// Shells usually expand globs before we see them, but not on Windows, or when the pattern is quoted.
+fn expand(input: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(input);
+    if path.exists() {
+        return Ok(vec![path.to_owned()]);
+    }
+    if !input.contains(['*', '?', '[']) {
+        return Err(format!("{} doesn't exist", input));
+    }
+    let matches = glob::glob(input)
+        .map_err(|e| format!("invalid pattern {}: {}", input, e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    if matches.is_empty() {
+        Err(format!("{} didn't match any files", input))
+    } else {
+        Ok(matches)
+    }
+}
+
+/// Write the files in `directory` to a new zip file at `zip`, returning how many files were included.
+///
+/// Files matched by `.gitignore` (and `.ignore`) files in the directory are left out, along with
+/// the `.git` directory and anything matched by the `excludes`. Ignore files outside the directory
+/// (in its parents, or the user's global git config) don't count, so the archive is the same for everyone.
+fn archive_directory(directory: &Path, excludes: &[&str], zip: &Path) -> io::Result<usize> {
+    let to_io_error = |e: ignore::Error| io::Error::other(e);
+    let mut overrides = OverrideBuilder::new(directory);
+    for exclude in excludes {
+        overrides.add(&format!("!{}", exclude)).map_err(to_io_error)?;
+    }
+    let walk = WalkBuilder::new(directory)
+        .hidden(false)
+        .parents(false)
+        .git_global(false)
+        .git_exclude(false)
+        .require_git(false)
+        .overrides(overrides.build().map_err(to_io_error)?)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut writer = ZipWriter::new(File::create(zip)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut file_count = 0;
+    for entry in walk {
+        let entry = entry.map_err(to_io_error)?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(directory).unwrap_or(entry.path());
+        let name = relative.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        writer.start_file(name, options)?;
+        io::copy(&mut File::open(entry.path())?, &mut writer)?;
+        file_count += 1;
+    }
+    writer.finish()?;
+    Ok(file_count)
+}
+
+#[test]
+fn test_gather_inputs(){
+    let root = std::env::temp_dir().join(format!("codedx-client-test-{}", std::process::id()));
+    let write = |path: &str, contents: &str| {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    };
+    write(".gitignore", "Main.java\n"); // outside the archived directory, so it doesn't apply
+    write("src/.gitignore", "target/\n*.log\n");
+    write("src/Main.java", "class Main {}");
+    write("src/util/Util.java", "class Util {}");
+    write("src/util/Generated.java", "class Generated {}");
+    write("src/debug.log", "ignored");
+    write("src/target/Main.class", "ignored");
+    write("src/.git/HEAD", "ignored");
+    write("reports/a.xml", "<a/>");
+    write("reports/b.xml", "<b/>");
+    write("reports/c.txt", "c");
+
+    let src = root.join("src");
+    let reports = root.join("reports").join("*.xml");
+    let inputs = AnalysisInputs::gather(&[src.to_str().unwrap(), reports.to_str().unwrap()], &["Generated.java"]).unwrap();
+
+    assert!(inputs.files().len() == 3);
+    assert!(inputs.files()[1].ends_with("reports/a.xml") && inputs.files()[2].ends_with("reports/b.xml"));
+    assert!(inputs.files()[0].file_name().unwrap() == "src.zip");
+    assert!(inputs.archives()[0].directory == src && inputs.archives()[0].file_count == 3);
+
+    let mut zip = zip::ZipArchive::new(File::open(inputs.files()[0]).unwrap()).unwrap();
+    let mut names: Vec<&str> = zip.file_names().collect();
+    names.sort_unstable();
+    assert!(names == vec![".gitignore", "Main.java", "util/Util.java"]);
+    assert!(zip.by_name("util/Util.java").unwrap().size() == 13);
+
+    let temp_dir = inputs.temp_dir.clone().unwrap();
+    drop(inputs);
+    assert!(!temp_dir.exists());
+
+    let missing = root.join("nope").join("*.xml");
+    assert!(AnalysisInputs::gather(&[missing.to_str().unwrap()], &[]).is_err());
+    assert!(AnalysisInputs::gather(&[root.join("nope.xml").to_str().unwrap()], &[]).is_err());
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -14,9 +14,15 @@
  * limitations under the License.
  */
 
+extern crate aes_gcm;
+extern crate glob;
+extern crate httpdate;
+extern crate ignore;
 extern crate reqwest;
 extern crate rpassword;
 extern crate serde;
+extern crate toml;
+extern crate zip;
 
 #[macro_use] extern crate clap;
 #[macro_use] extern crate nom;
@@ -27,7 +33,13 @@ mod branching;
 mod client;
 mod commands;
 mod config;
+mod credentials;
+mod diff;
+mod inputs;
+mod output;
+mod policy;
 mod repl;
+mod sarif;
 
 use clap::{ArgMatches, App, AppSettings};
 use std::io;
@@ -47,6 +59,21 @@ fn main(){
     };
     let matches = app.get_matches();
 
+    // logging out only needs the base url, and shouldn't be stopped by the lack of working credentials
+    if matches.subcommand_name() == Some("logout") {
+        let exit_code = match ClientConfig::base_url_from_matches(&matches) {
+            Ok(base_url) => match commands::logout(&base_url) {
+                Ok(()) => 0,
+                Err(commands::Exit(code)) => code,
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                commands::EXIT_CONFIG_ERROR
+            },
+        };
+        std::process::exit(exit_code);
+    }
+
     match ClientConfig::from_matches(&matches) {
         Ok(config) => {
             let client = ApiClient::new(Box::new(config));
@@ -63,9 +90,10 @@ fn main(){
                 run_repl(client);
             }
         },
-        Err(ConfigError::MissingAuth) => eprintln!("Authorization info missing or incomplete. Either an API Key or a Username + Password must be provided"),
-        Err(ConfigError::MissingUrl) => eprintln!("Missing the Base URL"),
-        Err(ConfigError::InvalidUrl) => eprintln!("Invalid Base URL. Did you forget 'http://' or 'https://' ?"),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(commands::EXIT_CONFIG_ERROR);
+        },
     }
 }
 
@@ -76,16 +104,17 @@ fn main(){
 fn run_oneoff<'a>(client: ApiClient, arg_matches: &ArgMatches<'a>) -> ! {
     let command_runner = CommandRunner(commands::all());
 
-    let exit_code = match command_runner.maybe_run(&arg_matches, &client) {
+    let exit_code = match command_runner.maybe_run(arg_matches, &client) {
         CommandRunnerResult::Done => 0,
         CommandRunnerResult::RequestedExit(code) => code,
+        // clap reports its own usage errors with code 1, so these do too
         CommandRunnerResult::UnknownCommand => {
             eprintln!("Unknown command.");
-            -1
+            commands::EXIT_ERROR
         },
         CommandRunnerResult::InvalidArguments(msg) => {
             eprintln!("Invalid arguments for command: {}", msg);
-            -2
+            commands::EXIT_ERROR
         },
     };
 
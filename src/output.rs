@@ -0,0 +1,187 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::ser::Serialize;
+use serde_json::Value;
+
+#[cfg(test)]
+use crate::str_vec;
+
+/// How commands print the records (projects, branches, findings, etc) that they produce.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// One compact JSON object per line
+    JsonLines,
+    /// A single compact JSON array
+    Json,
+    /// A single indented JSON array
+    PrettyJson,
+    /// Aligned columns with a header row
+    Table,
+    /// Comma-separated values with a header row
+    Csv,
+}
+impl OutputFormat {
+    pub const NAMES: &'static [&'static str] = &["jsonl", "json", "pretty", "table", "csv"];
+
+    pub fn parse(input: &str) -> Result<OutputFormat, &'static str> {
+        match input {
+            "jsonl" => Ok(OutputFormat::JsonLines),
+            "json" => Ok(OutputFormat::Json),
+            "pretty" => Ok(OutputFormat::PrettyJson),
+            "table" => Ok(OutputFormat::Table),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err("output format should be one of jsonl, json, pretty, table, or csv"),
+        }
+    }
+
+    /// Print a list of records to STDOUT.
+    pub fn print_records<T: Serialize>(&self, records: &[T]) {
+        let output = match *self {
+            OutputFormat::JsonLines => records.iter()
+                .map(|record| serde_json::to_string(record).unwrap() + "\n")
+                .collect(),
+            OutputFormat::Json => serde_json::to_string(records).unwrap() + "\n",
+            OutputFormat::PrettyJson => serde_json::to_string_pretty(records).unwrap() + "\n",
+            OutputFormat::Table | OutputFormat::Csv => {
+                let values: Vec<Value> = records.iter().map(|record| serde_json::to_value(record).unwrap()).collect();
+                let (header, rows) = to_rows(&values);
+                if *self == OutputFormat::Table { render_table(&header, &rows) } else { render_csv(&header, &rows) }
+            },
+        };
+        print!("{}", output);
+    }
+
+    /// Print a single record to STDOUT.
+    ///
+    /// The JSON formats print the record by itself rather than wrapped in an array.
+    pub fn print_record<T: Serialize>(&self, record: &T) {
+        match *self {
+            OutputFormat::JsonLines | OutputFormat::Json => println!("{}", serde_json::to_string(record).unwrap()),
+            OutputFormat::PrettyJson => println!("{}", serde_json::to_string_pretty(record).unwrap()),
+            OutputFormat::Table | OutputFormat::Csv => self.print_records(std::slice::from_ref(record)),
+        }
+    }
+}
+
+/// Flatten a list of JSON values into a header and rows of cells.
+///
+/// Columns are the keys of the objects, in order of first appearance. Nested objects are shown by
+/// their "name" if they have one (e.g. a finding's severity), as "path:line" if they look like a
+/// location, and as compact JSON otherwise.
+fn to_rows(values: &[Value]) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut header: Vec<String> = Vec::new();
+    for value in values {
+        if let Value::Object(ref map) = *value {
+            for key in map.keys() {
+                if !header.contains(key) {
+                    header.push(key.clone());
+                }
+            }
+        }
+    }
+    if header.is_empty() && !values.is_empty() {
+        // not a list of objects, so just put each value in a single "value" column
+        let rows = values.iter().map(|value| vec![to_cell(value)]).collect();
+        return (vec!["value".to_string()], rows);
+    }
+    let rows = values.iter().map(|value| {
+        header.iter().map(|key| value.get(key).map(to_cell).unwrap_or_default()).collect()
+    }).collect();
+    (header, rows)
+}
+
+fn to_cell(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref s) => s.clone(),
+        Value::Object(ref map) => match (map.get("name"), map.get("path")) {
+            (Some(Value::String(name)), _) => name.clone(),
+            // locations are shown as "path:line"
+            (_, Some(Value::String(path))) => match value.pointer("/lineRange/start") {
+                Some(line) => format!("{}:{}", path, line),
+                None => path.clone(),
+            },
+            _ => value.to_string(),
+        },
+        _ => value.to_string(),
+    }
+}
+
+fn render_table(header: &[String], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let render_line = |cells: &[String]| {
+        let padded: Vec<String> = cells.iter().zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        padded.join("  ").trim_end().to_string() + "\n"
+    };
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    let mut output = render_line(header);
+    output += &render_line(&separator);
+    for row in rows {
+        output += &render_line(row);
+    }
+    output
+}
+
+fn render_csv(header: &[String], rows: &[Vec<String>]) -> String {
+    let render_line = |cells: &[String]| {
+        let escaped: Vec<String> = cells.iter().map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.clone()
+            }
+        }).collect();
+        escaped.join(",") + "\n"
+    };
+    let mut output = render_line(header);
+    for row in rows {
+        output += &render_line(row);
+    }
+    output
+}
+
+#[test]
+fn test_rows_from_objects(){
+    let values = vec![
+        json!({ "id": 1, "name": "main", "severity": { "id": 3, "name": "High" } }),
+        json!({ "id": 2, "name": null, "extra": true, "location": { "path": "a.c", "lineRange": { "start": 7 } } }),
+    ];
+    let (header, rows) = to_rows(&values);
+    assert!(header == str_vec!["id", "name", "severity", "extra", "location"]);
+    assert!(rows == vec![str_vec!["1", "main", "High", "", ""], str_vec!["2", "", "", "true", "a.c:7"]]);
+}
+
+#[test]
+fn test_render_table(){
+    let header = ["id", "name"].map(String::from);
+    let table = render_table(&header, &[str_vec!["1", "main"], str_vec!["22", "x"]]);
+    assert!(table == "id  name\n--  ----\n1   main\n22  x\n");
+}
+
+#[test]
+fn test_render_csv_escaping(){
+    let header = ["id", "name"].map(String::from);
+    let csv = render_csv(&header, &[str_vec!["1", "a, \"b\""]]);
+    assert!(csv == "id,name\n1,\"a, \"\"b\"\"\"\n");
+}
@@ -0,0 +1,253 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::client::ApiFindingFilter;
+use std::fmt;
+
+/// Comparison operator used by a `FailCondition` clause.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Eq,
+    NotEq,
+    Gt,
+    GtEq,
+    Lt,
+    LtEq,
+}
+impl Comparison {
+    fn test<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match *self {
+            Comparison::Eq => left == right,
+            Comparison::NotEq => left != right,
+            Comparison::Gt => left > right,
+            Comparison::GtEq => left >= right,
+            Comparison::Lt => left < right,
+            Comparison::LtEq => left <= right,
+        }
+    }
+}
+
+/// Severities in ascending order, as Code Dx names them.
+const SEVERITY_ORDER: &[&str] = &["Info", "Low", "Medium", "High", "Critical"];
+/// The severity of findings that weren't given one, which ranks below all of the others.
+const UNRANKED_SEVERITY: &str = "Unspecified";
+
+fn severity_rank(name: &str) -> Option<usize> {
+    SEVERITY_ORDER.iter().position(|s| s.eq_ignore_ascii_case(name))
+}
+
+/// A single `<field><op><value>` criterion that a finding either matches or doesn't.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FindingCriterion {
+    Severity(Comparison, usize),
+    Status(Comparison, String),
+}
+
+/// How to count the findings that match a `FailCondition`'s criteria: the number matching `filter`,
+/// minus the number matching each of the `excluded` filters.
+///
+/// Each `excluded` filter is for a different status, so no finding is subtracted twice.
+#[derive(Debug)]
+pub struct FindingCount<'a> {
+    pub filter: ApiFindingFilter<'a>,
+    pub excluded: Vec<ApiFindingFilter<'a>>,
+}
+
+/// A build-breaking condition, as given to `analyze --fail-on`.
+///
+/// Written as a comma-separated list of clauses, e.g. `severity>=high,status=new,count>10`.
+/// The condition is met when the number of findings matching every `severity` and `status`
+/// clause satisfies the `count` clause. When no `count` clause is given, it defaults to `count>0`,
+/// i.e. any matching finding meets the condition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailCondition {
+    pub criteria: Vec<FindingCriterion>,
+    pub count: (Comparison, usize),
+    source: String,
+}
+impl FailCondition {
+    pub fn parse(input: &str) -> Result<FailCondition, &'static str> {
+        let mut criteria = Vec::new();
+        let mut count = None;
+        for clause in input.split(',').map(str::trim) {
+            let (field, op, value) = split_clause(clause)?;
+            match field.to_lowercase().as_str() {
+                "severity" => {
+                    let rank = severity_rank(value).ok_or("severity should be one of info, low, medium, high, or critical")?;
+                    criteria.push(FindingCriterion::Severity(op, rank));
+                },
+                "status" => {
+                    if op != Comparison::Eq && op != Comparison::NotEq {
+                        return Err("status can only be compared with '=' or '!='");
+                    }
+                    criteria.push(FindingCriterion::Status(op, value.to_lowercase()));
+                },
+                "count" => {
+                    let n = value.parse::<usize>().map_err(|_| "count should be a number")?;
+                    count = Some((op, n));
+                },
+                _ => return Err("fail-on conditions may only use severity, status, and count"),
+            }
+        }
+        Ok(FailCondition {
+            criteria,
+            count: count.unwrap_or((Comparison::Gt, 0)),
+            source: input.to_string(),
+        })
+    }
+
+    /// The finding filters to count with, so that the findings themselves don't need to be downloaded.
+    ///
+    /// Returns `None` if the criteria contradict each other (e.g. `status=new,status=fixed`), so no finding could match.
+    pub fn count_query(&self) -> Option<FindingCount<'_>> {
+        let mut severities: Vec<&str> = SEVERITY_ORDER.to_vec();
+        severities.insert(0, UNRANKED_SEVERITY);
+        let mut status: Option<&str> = None;
+        let mut excluded_statuses: Vec<&str> = Vec::new();
+        for criterion in &self.criteria {
+            match *criterion {
+                FindingCriterion::Severity(op, rank) => {
+                    // findings with an unknown severity rank below everything
+                    severities.retain(|s| op.test(severity_rank(s).map(|r| r as isize).unwrap_or(-1), rank as isize));
+                },
+                FindingCriterion::Status(Comparison::Eq, ref value) => match status {
+                    Some(existing) if existing != value => return None,
+                    _ => status = Some(value),
+                },
+                FindingCriterion::Status(_, ref value) => {
+                    if !excluded_statuses.contains(&value.as_str()) {
+                        excluded_statuses.push(value);
+                    }
+                },
+            }
+        }
+        if severities.is_empty() {
+            return None;
+        }
+        if let Some(status) = status {
+            // a single status leaves out all of the others already
+            if excluded_statuses.contains(&status) {
+                return None;
+            }
+            excluded_statuses.clear();
+        }
+
+        let severity = if severities.len() > SEVERITY_ORDER.len() { None } else { Some(severities) };
+        fn filter_for<'a>(severity: &Option<Vec<&'a str>>, status: Option<&'a str>) -> ApiFindingFilter<'a> {
+            ApiFindingFilter {
+                severity: severity.clone(),
+                status: status.map(|status| vec![status]),
+                ..ApiFindingFilter::default()
+            }
+        }
+        Some(FindingCount {
+            filter: filter_for(&severity, status),
+            excluded: excluded_statuses.iter().map(|status| filter_for(&severity, Some(status))).collect(),
+        })
+    }
+
+    /// Check the number of findings matching this condition's criteria against its count threshold,
+    /// returning that number if the condition is met, or `None` if it isn't.
+    pub fn evaluate(&self, num_matching: usize) -> Option<usize> {
+        let (op, threshold) = self.count;
+        if op.test(num_matching, threshold) { Some(num_matching) } else { None }
+    }
+}
+impl fmt::Display for FailCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Split a clause like `severity>=high` into its field, operator, and value.
+fn split_clause(clause: &str) -> Result<(&str, Comparison, &str), &'static str> {
+    let op_start = clause.find(['=', '!', '<', '>'])
+        .ok_or("fail-on clauses should look like <field><operator><value>, e.g. severity>=high")?;
+    let (field, rest) = clause.split_at(op_start);
+    // check the two-character operators first so that e.g. ">=" isn't read as ">"
+    let ops = [
+        (">=", Comparison::GtEq),
+        ("<=", Comparison::LtEq),
+        ("!=", Comparison::NotEq),
+        ("==", Comparison::Eq),
+        (">", Comparison::Gt),
+        ("<", Comparison::Lt),
+        ("=", Comparison::Eq),
+    ];
+    let (op_str, op) = ops.iter()
+        .find(|(op_str, _)| rest.starts_with(op_str))
+        .ok_or("unrecognized operator in fail-on clause")?;
+    let value = rest[op_str.len()..].trim();
+    if field.trim().is_empty() || value.is_empty() {
+        Err("fail-on clauses should look like <field><operator><value>, e.g. severity>=high")
+    } else {
+        Ok((field.trim(), *op, value))
+    }
+}
+
+#[test]
+fn test_parse_severity(){
+    let cond = FailCondition::parse("severity>=high").unwrap();
+    assert!(cond.criteria == vec![FindingCriterion::Severity(Comparison::GtEq, 3)]);
+    assert!(cond.count == (Comparison::Gt, 0));
+}
+
+#[test]
+fn test_parse_combined(){
+    let cond = FailCondition::parse("severity>=High, status=New, count>10").unwrap();
+    assert!(cond.criteria == vec![
+        FindingCriterion::Severity(Comparison::GtEq, 3),
+        FindingCriterion::Status(Comparison::Eq, "new".to_string()),
+    ]);
+    assert!(cond.count == (Comparison::Gt, 10));
+}
+
+#[test]
+fn test_parse_count_only(){
+    let cond = FailCondition::parse("count>=5").unwrap();
+    assert!(cond.criteria.is_empty());
+    assert!(cond.count == (Comparison::GtEq, 5));
+}
+
+#[test]
+fn test_parse_invalid(){
+    assert!(FailCondition::parse("severity>=extreme").is_err());
+    assert!(FailCondition::parse("status>new").is_err());
+    assert!(FailCondition::parse("count>lots").is_err());
+    assert!(FailCondition::parse("color=red").is_err());
+    assert!(FailCondition::parse("severity").is_err());
+}
+
+#[test]
+fn test_count_query(){
+    let cond = FailCondition::parse("severity>=high,status!=fixed,status!=gone").unwrap();
+    let query = cond.count_query().unwrap();
+    assert!(query.filter.severity == Some(vec!["High", "Critical"]) && query.filter.status.is_none());
+    assert!(query.excluded.iter().map(|f| f.status.clone().unwrap()).collect::<Vec<_>>() == vec![vec!["fixed"], vec!["gone"]]);
+    assert!(query.excluded.iter().all(|f| f.severity == query.filter.severity));
+
+    let cond = FailCondition::parse("severity<medium,status=new,status!=fixed").unwrap();
+    let query = cond.count_query().unwrap();
+    assert!(query.filter.severity == Some(vec!["Unspecified", "Info", "Low"]) && query.filter.status == Some(vec!["new"]));
+    assert!(query.excluded.is_empty());
+
+    assert!(FailCondition::parse("count>5").unwrap().count_query().unwrap().filter.is_empty());
+    assert!(FailCondition::parse("status=new,status=fixed").unwrap().count_query().is_none());
+    assert!(FailCondition::parse("status=new,status!=new").unwrap().count_query().is_none());
+    assert!(FailCondition::parse("severity>critical").unwrap().count_query().is_none());
+    assert!(FailCondition::parse("count>=2").unwrap().evaluate(2) == Some(2));
+    assert!(FailCondition::parse("count>=2").unwrap().evaluate(1).is_none());
+}
@@ -14,7 +14,6 @@
  * limitations under the License.
  */
 
-use std;
 use std::str;
 use std::str::FromStr;
 
@@ -39,7 +38,7 @@ impl FromStr for CmdArgs {
     fn from_str(s: &str) -> Result<CmdArgs, ()> {
         arg_list(s.as_ref())
             .to_result()
-            .map(|v| CmdArgs(v))
+            .map(CmdArgs)
             .map_err(|_| ())
     }
 }
@@ -116,8 +115,8 @@ named!(one_arg <String>, alt!(dq_string | sq_string | consecutive_string));
 named!(arg_list<Vec<String>>, separated_list!(is_a!(" \t"), one_arg));
 
 #[cfg(test)]
-fn test_parse(s: &str) -> Result<Vec<String>, ::nom::Err<&[u8]>> {
-    arg_list(s.as_ref())
+fn test_parse(s: &str) -> Result<Vec<String>, ::nom::ErrorKind> {
+    arg_list(s.as_ref()).to_result()
 }
 
 #[test]
@@ -0,0 +1,217 @@
+/*
+ * Copyright 2021 Code Dx, Inc
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::client::{ApiFinding, ApiFindingLocation};
+use serde_json::Value;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Convert findings (ideally with the details from `ApiClient::get_finding`) into a SARIF 2.1.0 log.
+///
+/// The log has a single run whose tool is Code Dx, with one rule per finding descriptor.
+/// The tools that originally reported each finding are listed in the result's `properties.tools`.
+pub fn to_sarif(findings: &[ApiFinding]) -> Value {
+    let mut rules: Vec<Value> = Vec::new();
+    let mut rule_ids: Vec<String> = Vec::new();
+    let mut results = Vec::new();
+
+    for finding in findings {
+        let rule_id = rule_id(finding);
+        let rule_index = match rule_ids.iter().position(|id| *id == rule_id) {
+            Some(index) => index,
+            None => {
+                rules.push(rule(&rule_id, finding));
+                rule_ids.push(rule_id.clone());
+                rule_ids.len() - 1
+            },
+        };
+        // code scanning tools only look for the security-severity on the rule, so a rule
+        // shared by several findings gets the highest of their severities
+        let severity = security_severity(finding.severity.as_ref().map(|s| s.name.as_str()));
+        let properties = &mut rules[rule_index]["properties"];
+        if severity_score(severity) > severity_score(properties["security-severity"].as_str()) {
+            properties["security-severity"] = json!(severity);
+        }
+        results.push(result(&rule_id, rule_index, finding));
+    }
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "Code Dx",
+                    "informationUri": "https://codedx.com",
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// Findings for the same descriptor share a rule; findings without one get a rule per finding.
+fn rule_id(finding: &ApiFinding) -> String {
+    match finding.descriptor {
+        Some(ref descriptor) => match descriptor.id {
+            Some(id) => id.to_string(),
+            None => descriptor.name.clone(),
+        },
+        None => format!("finding-{}", finding.id),
+    }
+}
+
+fn rule(rule_id: &str, finding: &ApiFinding) -> Value {
+    let name = finding.descriptor.as_ref().map(|d| d.name.as_str()).unwrap_or(rule_id);
+    let mut rule = json!({
+        "id": rule_id,
+        "name": name,
+        "shortDescription": { "text": name },
+    });
+    if let Some(ref description) = finding.description {
+        rule["fullDescription"] = json!({ "text": description });
+    }
+    let mut tags = vec!["security".to_string()];
+    if let Some(cwe) = finding.cwe {
+        tags.push(format!("external/cwe/cwe-{}", cwe));
+    }
+    rule["properties"] = json!({ "tags": tags });
+    rule
+}
+
+fn result(rule_id: &str, rule_index: usize, finding: &ApiFinding) -> Value {
+    let severity = finding.severity.as_ref().map(|s| s.name.as_str());
+    // the tools' own messages say the most about this particular finding; fall back to the rule name
+    let message = finding.results.iter()
+        .find_map(|result| result.message.clone())
+        .or_else(|| finding.descriptor.as_ref().map(|d| d.name.clone()))
+        .unwrap_or_else(|| format!("Code Dx finding {}", finding.id));
+    let mut tools: Vec<&str> = finding.results.iter().filter_map(|result| result.tool.as_deref()).collect();
+    tools.sort_unstable();
+    tools.dedup();
+
+    let mut result = json!({
+        "ruleId": rule_id,
+        "ruleIndex": rule_index,
+        "level": level(severity),
+        "message": { "text": message },
+    });
+    if let Some(ref location) = finding.primary_location {
+        result["locations"] = json!([physical_location(location)]);
+    }
+    let related: Vec<Value> = finding.locations.iter()
+        .filter(|location| !is_same_location(Some(*location), finding.primary_location.as_ref()))
+        .enumerate()
+        .map(|(index, location)| {
+            let mut related = physical_location(location);
+            related["id"] = json!(index + 1);
+            related
+        })
+        .collect();
+    if !related.is_empty() {
+        result["relatedLocations"] = json!(related);
+    }
+    result["partialFingerprints"] = json!({ "codedxFindingId": finding.id.to_string() });
+    result["properties"] = json!({
+        "codedxFindingId": finding.id,
+        "severity": severity,
+        "status": finding.status,
+        "tools": tools,
+    });
+    result
+}
+
+fn physical_location(location: &ApiFindingLocation) -> Value {
+    let mut physical = json!({ "artifactLocation": { "uri": location.path.replace('\\', "/") } });
+    if let Some(ref lines) = location.line_range {
+        physical["region"] = json!({ "startLine": lines.start, "endLine": lines.end });
+    }
+    json!({ "physicalLocation": physical })
+}
+
+fn is_same_location(a: Option<&ApiFindingLocation>, b: Option<&ApiFindingLocation>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.path == b.path
+            && a.line_range.as_ref().map(|r| (r.start, r.end)) == b.line_range.as_ref().map(|r| (r.start, r.end)),
+        _ => false,
+    }
+}
+
+/// SARIF only has three levels of result, so Code Dx's five severities are squeezed into them.
+fn level(severity: Option<&str>) -> &'static str {
+    match severity.map(|s| s.to_lowercase()).as_deref() {
+        Some("critical") | Some("high") => "error",
+        Some("medium") => "warning",
+        _ => "note",
+    }
+}
+
+/// A CVSS-like score for the severity, which code scanning tools (e.g. GitHub's) use to rank rules.
+fn security_severity(severity: Option<&str>) -> Option<&'static str> {
+    match severity.map(|s| s.to_lowercase()).as_deref() {
+        Some("critical") => Some("9.5"),
+        Some("high") => Some("8.0"),
+        Some("medium") => Some("5.5"),
+        Some("low") => Some("2.0"),
+        Some("info") => Some("0.0"),
+        _ => None,
+    }
+}
+
+fn severity_score(security_severity: Option<&str>) -> Option<f64> {
+    security_severity.and_then(|score| score.parse::<f64>().ok())
+}
+
+#[test]
+fn test_to_sarif(){
+    let findings: Vec<ApiFinding> = serde_json::from_value(json!([
+        {
+            "id": 1021, "descriptor": {"id": 88, "name": "SQL Injection"}, "severity": {"id": 5, "name": "Critical"},
+            "status": "new", "detectionMethod": null,
+            "primaryLocation": {"path": "src\\Db.java", "lineRange": {"start": 12, "end": 14}},
+            "cwe": 89, "description": "Untrusted data is used to build a SQL query.",
+            "locations": [
+                {"path": "src\\Db.java", "lineRange": {"start": 12, "end": 14}},
+                {"path": "src/Api.java", "lineRange": {"start": 40, "end": 40}}
+            ],
+            "results": [{"id": 1, "tool": "FindBugs", "rule": "SQL_INJECTION_JDBC", "severity": null, "message": "Query built from request parameter", "location": null}]
+        },
+        {
+            "id": 1022, "descriptor": {"id": 88, "name": "SQL Injection"}, "severity": {"id": 2, "name": "Low"},
+            "status": "escalated", "detectionMethod": null, "primaryLocation": null
+        }
+    ])).unwrap();
+
+    let log = to_sarif(&findings);
+    let run = &log["runs"][0];
+    let first = &run["results"][0];
+    let second = &run["results"][1];
+
+    assert!(log["version"] == "2.1.0");
+    assert!(run["tool"]["driver"]["rules"].as_array().unwrap().len() == 1);
+    assert!(run["tool"]["driver"]["rules"][0]["properties"]["tags"][1] == "external/cwe/cwe-89");
+    assert!(run["tool"]["driver"]["rules"][0]["properties"]["security-severity"] == "9.5");
+    assert!(first["properties"].get("security-severity").is_none());
+    assert!(first["ruleId"] == "88" && first["ruleIndex"] == 0 && first["level"] == "error");
+    assert!(first["message"]["text"] == "Query built from request parameter");
+    assert!(first["locations"][0]["physicalLocation"]["artifactLocation"]["uri"] == "src/Db.java");
+    assert!(first["locations"][0]["physicalLocation"]["region"]["startLine"] == 12);
+    assert!(first["relatedLocations"].as_array().unwrap().len() == 1);
+    assert!(first["properties"]["tools"][0] == "FindBugs");
+    assert!(second["ruleIndex"] == 0 && second["level"] == "note" && second["message"]["text"] == "SQL Injection");
+    assert!(second.get("locations").is_none());
+}